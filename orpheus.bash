@@ -0,0 +1,15 @@
+# Bash completion for Orpheus CLI
+_orpheus() {
+    local IFS=$'\n'
+
+    # Ask orpheus, passing the words after `orpheus` up to and including
+    # the one being completed
+    COMPREPLY=($(orpheus --complete "${COMP_WORDS[@]:1:COMP_CWORD}" 2>/dev/null))
+
+    # Directories end in /, keep completing into them
+    if [[ ${#COMPREPLY[@]} -eq 1 && ${COMPREPLY[0]} == */ ]]; then
+        compopt -o nospace
+    fi
+}
+
+complete -F _orpheus orpheus
@@ -0,0 +1,4 @@
+# Fish completion for Orpheus CLI
+
+# Ask orpheus, passing the words after `orpheus` and the one being completed
+complete -c orpheus -f -a '(orpheus --complete (commandline -opc)[2..] (commandline -ct) 2>/dev/null)'
@@ -0,0 +1,138 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::Command;
+use crate::config::CONFIG;
+use crate::playlist::list_playlists;
+
+/// Candidates for the last of `words`, the arguments after `orpheus` with the
+/// one being completed last (empty at a new word). This is what the shell
+/// scripts next to the source call as `orpheus --complete ...`.
+pub fn complete(words: &[String]) -> Vec<String> {
+    let (current, before) = match words.split_last() {
+        Some((current, before)) => (current.as_str(), before),
+        None => ("", words),
+    };
+    let mut candidates = candidates(before, current);
+    candidates.retain(|c| c.starts_with(current));
+    candidates.sort();
+    candidates.dedup();
+    candidates
+}
+
+fn candidates(before: &[String], current: &str) -> Vec<String> {
+    let Some((command, args)) = before.split_first() else {
+        return words(Command::all());
+    };
+    let previous = args.last().map(String::as_str);
+    match command.as_str() {
+        "create" => match args.len() {
+            0 => Vec::new(),
+            _ if matches!(previous, Some("--query" | "-q" | "--smart")) => Vec::new(),
+            _ => [words(&["--query", "--smart", "-"]), paths(current, true)].concat(),
+        },
+        "playlist" => match (args.first().map(String::as_str), args.len()) {
+            (_, 0) => words(&["add", "remove", "show", "rename", "copy"]),
+            (Some(_), 1) => playlists(),
+            (Some("add"), _) => paths(current, true),
+            _ => Vec::new(),
+        },
+        "edit" if args.is_empty() => playlists(),
+        "delete" => playlists(),
+        "play" | "append" | "playnext" => match previous {
+            Some("--shuffle") => words(&["tracks", "album", "artist", "weighted"]),
+            Some("--query" | "-q" | "--seed") => Vec::new(),
+            _ => {
+                let mut flags = words(&["--query", "--shuffle", "--seed"]);
+                if command == "append" {
+                    flags.push("--next".into());
+                }
+                if args.is_empty() {
+                    flags.extend(words(&["album", "artist", "folder"]));
+                }
+                [flags, playlists(), paths(current, true)].concat()
+            }
+        },
+        "queue" => match (args.first().map(String::as_str), args.len()) {
+            (_, 0) => words(&["list", "remove", "move", "clear", "save", "shuffle"]),
+            (Some("clear"), 1) => words(&["--keep-current"]),
+            _ => Vec::new(),
+        },
+        "session" if args.is_empty() => words(&["save", "restore"]),
+        "shuffle" if args.is_empty() => words(&["true", "false"]),
+        "repeat" if args.is_empty() => words(&["off", "track", "queue"]),
+        "mute" if args.is_empty() => words(&["on", "off", "toggle"]),
+        "status" => words(&["--json"]),
+        "scan" => words(&["--full", "--dry-run"]),
+        "rescan" => words(&["--dry-run"]),
+        "import" if args.is_empty() => paths(current, false),
+        "export" => match previous {
+            None => playlists(),
+            Some("--format" | "-f") => words(&["m3u", "m3u8", "pls", "xspf", "json"]),
+            Some("--output" | "-o") => paths(current, false),
+            _ => words(&["--format", "--output"]),
+        },
+        _ => Vec::new(),
+    }
+}
+
+fn words(words: &[&str]) -> Vec<String> {
+    words.iter().map(|w| w.to_string()).collect()
+}
+
+/// Playlist names as commands take them, without the extension.
+fn playlists() -> Vec<String> {
+    list_playlists()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|p| p.file_stem())
+        .map(|s| s.to_string_lossy().into_owned())
+        .collect()
+}
+
+/// Files and directories starting with `current`, directories with a
+/// trailing `/` so the shell keeps completing into them. With `music` the
+/// music directories are offered too, and alone before anything is typed.
+fn paths(current: &str, music: bool) -> Vec<String> {
+    let mut found = Vec::new();
+    if music {
+        let config = CONFIG.get().expect("config not initialized");
+        found.extend(
+            config
+                .music_dirs
+                .iter()
+                .map(|dir| format!("{}/", dir.display())),
+        );
+        if current.is_empty() {
+            return found;
+        }
+    }
+
+    let (dir, name) = match current.rfind('/') {
+        Some(i) => current.split_at(i + 1),
+        None => ("", current),
+    };
+    let Ok(entries) = fs::read_dir(expand_home(dir)) else {
+        return found;
+    };
+    found.extend(entries.filter_map(Result::ok).filter_map(|entry| {
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if !file_name.starts_with(name) || file_name.starts_with('.') && !name.starts_with('.') {
+            return None;
+        }
+        // Follows symlinks, so linked directories complete like real ones.
+        let slash = if entry.path().is_dir() { "/" } else { "" };
+        Some(format!("{}{}{}", dir, file_name, slash))
+    }));
+    found
+}
+
+/// Shells pass `~/Music/` unexpanded while the word is being completed.
+fn expand_home(dir: &str) -> PathBuf {
+    match (dir.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => Path::new(&home).join(rest),
+        _ if dir.is_empty() => PathBuf::from("."),
+        _ => PathBuf::from(dir),
+    }
+}
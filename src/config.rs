@@ -5,6 +5,7 @@ use std::io::{self, Write};
 use std::path::PathBuf;
 
 use std::sync::OnceLock;
+use std::thread;
 
 pub static CONFIG: OnceLock<Config> = OnceLock::new();
 
@@ -12,14 +13,25 @@ pub static CONFIG: OnceLock<Config> = OnceLock::new();
 pub struct Config {
     pub socket_path: PathBuf,
     pub mpris_plugin_path: Option<PathBuf>,
-    pub music_dir: PathBuf,
+    /// Music roots, scanned in order. Never empty.
+    pub music_dirs: Vec<PathBuf>,
+    /// Gitignore-style patterns of files and directories to skip while scanning.
+    pub exclude: Vec<String>,
+    /// Descend into symlinked directories while scanning.
+    pub follow_symlinks: bool,
+    /// Number of threads walking the music roots.
+    pub scan_threads: usize,
+    /// Upper bound for `volume` changes, in percent.
+    pub max_volume: f64,
+    /// Restore the last volume set through orpheus when spawning mpv.
+    pub restore_volume: bool,
 }
 
 impl Config {
     pub fn load() -> io::Result<Self> {
         let home_dir = env::var("HOME")
             .map(PathBuf::from)
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            .map_err(|_| io::Error::other("HOME is not set"))?;
         let default_socket = PathBuf::from("/tmp/mpv-socket");
         let default_music = home_dir.join("Music");
 
@@ -29,12 +41,14 @@ impl Config {
         let config_dir = xdg_config.join("orpheus");
         let config_path = config_dir.join("config.conf");
 
+        let in_path =
+            |e: io::Error| io::Error::new(e.kind(), format!("{}: {}", config_path.display(), e));
         if !config_dir.exists() {
-            fs::create_dir_all(&config_dir)?;
+            fs::create_dir_all(&config_dir).map_err(in_path)?;
         }
 
         if !config_path.exists() {
-            let mut file = File::create(&config_path)?;
+            let mut file = File::create(&config_path).map_err(in_path)?;
             writeln!(file, "# Orpheus configuration file")?;
             writeln!(file, "# socket_path=/tmp/mpv-socket")?;
             writeln!(
@@ -42,9 +56,20 @@ impl Config {
                 "# mpris_plugin_path=/usr/lib/mpv-mpris/mpris.so # Optional plugin - allows to use media keys"
             )?;
             writeln!(file, "# music_dir=$HOME/Music")?;
+            writeln!(
+                file,
+                "# music_dir=/mnt/nas/Music # Repeat music_dir to add more roots"
+            )?;
+            writeln!(file, "# exclude=**/Samples/**")?;
+            writeln!(file, "# exclude=*.part")?;
+            writeln!(file, "# follow_symlinks=true")?;
+            writeln!(file, "# scan_threads=8 # Defaults to the number of CPUs")?;
+            writeln!(file, "# max_volume=100")?;
+            writeln!(file, "# restore_volume=true")?;
         }
 
-        let mut config_map = HashMap::new();
+        // Keys may be repeated; single-valued keys use the last occurrence.
+        let mut config_map: HashMap<String, Vec<String>> = HashMap::new();
         if let Ok(content) = fs::read_to_string(&config_path) {
             for line in content.lines() {
                 let line = line.trim();
@@ -52,37 +77,79 @@ impl Config {
                     continue;
                 }
                 if let Some((key, value)) = line.split_once('=') {
-                    config_map.insert(key.trim().to_string(), value.trim().to_string());
+                    config_map
+                        .entry(key.trim().to_string())
+                        .or_default()
+                        .push(value.trim().to_string());
                 }
             }
         }
+        let get = |key: &str| config_map.get(key).and_then(|values| values.last());
 
-        let socket_path = config_map
-            .get("socket_path")
+        let socket_path = get("socket_path")
             .map(|v| expand_env_vars(v))
             .unwrap_or(default_socket);
 
-        let music_dir = config_map
+        let mut music_dirs: Vec<PathBuf> = config_map
             .get("music_dir")
+            .into_iter()
+            .flatten()
             .map(|v| expand_env_vars(v))
-            .unwrap_or(default_music);
+            .collect();
+        if music_dirs.is_empty() {
+            music_dirs.push(default_music);
+        }
+
+        let exclude = config_map.get("exclude").cloned().unwrap_or_default();
+
+        let follow_symlinks = get("follow_symlinks")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(true);
 
-        let mpris_plugin_path = config_map
-            .get("mpris_plugin_path")
+        let scan_threads = get("scan_threads")
+            .and_then(|v| v.parse().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+
+        let mpris_plugin_path = get("mpris_plugin_path")
             .map(|v| expand_env_vars(v))
-            .and_then(|p| {
-                let path = PathBuf::from(p);
-                if path.exists() { Some(path) } else { None }
-            });
+            .filter(|path| path.exists());
+
+        let max_volume = get("max_volume")
+            .and_then(|v| v.parse::<f64>().ok())
+            .filter(|v| *v > 0.0)
+            .unwrap_or(100.0);
+
+        let restore_volume = get("restore_volume")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(true);
 
         Ok(Self {
             socket_path,
             mpris_plugin_path,
-            music_dir,
+            music_dirs,
+            exclude,
+            follow_symlinks,
+            scan_threads,
+            max_volume,
+            restore_volume,
         })
     }
 }
 
+/// Orpheus data directory (`$XDG_DATA_HOME/orpheus`), created on first use.
+pub fn data_dir() -> PathBuf {
+    let data_dir = env::var("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            let home = env::var("HOME").expect("HOME env var not set");
+            PathBuf::from(home).join(".local/share")
+        });
+    let orpheus_dir = data_dir.join("orpheus");
+    fs::create_dir_all(&orpheus_dir).unwrap();
+    orpheus_dir
+}
+
 fn expand_env_vars(path: &str) -> PathBuf {
     let mut result = String::new();
     let mut chars = path.chars().peekable();
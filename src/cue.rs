@@ -0,0 +1,224 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::tags::Tags;
+
+/// A track indexed in a CUE sheet.
+#[derive(Debug, Clone)]
+pub struct CueTrack {
+    /// The audio file the track lives in, resolved against the sheet's directory.
+    pub file: PathBuf,
+    pub tags: Tags,
+    /// Offset of `INDEX 01` in seconds.
+    pub start: f64,
+    /// Start of the next track in the same file; `None` for the last one.
+    pub end: Option<f64>,
+}
+
+pub fn is_cue_sheet(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("cue"))
+}
+
+pub fn read(path: &Path) -> io::Result<Vec<CueTrack>> {
+    let bytes = fs::read(path)?;
+    // Older rippers write sheets in Latin-1.
+    let content = match String::from_utf8(bytes) {
+        Ok(content) => content,
+        Err(e) => e.into_bytes().iter().map(|&b| b as char).collect(),
+    };
+    Ok(parse(&content, path.parent().unwrap_or(Path::new("."))))
+}
+
+/// Splits a CUE command line into its words, honoring double quotes.
+fn words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut chars = line.trim().chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            words.push(chars.by_ref().take_while(|&c| c != '"').collect());
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            words.push(word);
+        }
+    }
+    words
+}
+
+/// `mm:ss:ff` with 75 frames per second.
+fn parse_index(time: &str) -> Option<f64> {
+    let mut parts = time.split(':').map(|p| p.parse::<u32>().ok());
+    let (m, s, f) = (parts.next()??, parts.next()??, parts.next()??);
+    Some(f64::from(m) * 60.0 + f64::from(s) + f64::from(f) / 75.0)
+}
+
+/// Tags of the track being parsed, or the album before the first TRACK.
+fn tags_of<'a>(current: &'a mut Option<CueTrack>, album: &'a mut Tags) -> &'a mut Tags {
+    match current {
+        Some(track) => &mut track.tags,
+        None => album,
+    }
+}
+
+pub fn parse(content: &str, base: &Path) -> Vec<CueTrack> {
+    // Album-level values, inherited by every track.
+    let mut album = Tags::default();
+    let mut file: Option<PathBuf> = None;
+    let mut tracks: Vec<CueTrack> = Vec::new();
+    let mut current: Option<CueTrack> = None;
+
+    for line in content.lines() {
+        let words = words(line.trim_start_matches('\u{feff}'));
+        let Some(command) = words.first() else {
+            continue;
+        };
+        let arg = |i: usize| words.get(i).cloned().filter(|w| !w.is_empty());
+
+        match command.to_ascii_uppercase().as_str() {
+            "FILE" => file = arg(1).map(|f| base.join(f)),
+            "TRACK" => {
+                tracks.extend(current.take().filter(|t| t.start >= 0.0));
+                let Some(file) = file.clone() else {
+                    continue;
+                };
+                current = Some(CueTrack {
+                    file,
+                    tags: Tags {
+                        artist: album.artist.clone(),
+                        album: album.album.clone(),
+                        year: album.year,
+                        genre: album.genre.clone(),
+                        track: arg(1).and_then(|n| n.parse().ok()),
+                        title: None,
+                        ..Tags::default()
+                    },
+                    // Filled in by INDEX 01.
+                    start: -1.0,
+                    end: None,
+                });
+            }
+            "TITLE" => match current.as_mut() {
+                Some(track) => track.tags.title = arg(1),
+                None => album.album = arg(1),
+            },
+            "PERFORMER" => tags_of(&mut current, &mut album).artist = arg(1),
+            "INDEX" if arg(1).as_deref() == Some("01") => {
+                if let (Some(track), Some(start)) =
+                    (current.as_mut(), arg(2).as_deref().and_then(parse_index))
+                {
+                    track.start = start;
+                }
+            }
+            "REM" => {
+                let tags = tags_of(&mut current, &mut album);
+                match arg(1).map(|k| k.to_ascii_uppercase()).as_deref() {
+                    Some("DATE") => {
+                        tags.year = arg(2).and_then(|d| d.get(..4).and_then(|y| y.parse().ok()))
+                    }
+                    Some("GENRE") => tags.genre = arg(2),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    tracks.extend(current.filter(|t| t.start >= 0.0));
+
+    // A track ends where the next one in the same file starts.
+    for i in 1..tracks.len() {
+        if tracks[i].file == tracks[i - 1].file {
+            tracks[i - 1].end = Some(tracks[i].start);
+        }
+    }
+    tracks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHEET: &str = "\u{feff}REM GENRE Jazz\n\
+        REM DATE 1959-08-17\n\
+        PERFORMER \"Miles Davis\"\n\
+        TITLE \"Kind of Blue\"\n\
+        FILE \"side a.flac\" WAVE\n\
+        \x20 TRACK 01 AUDIO\n\
+        \x20   TITLE \"So What\"\n\
+        \x20   INDEX 01 00:00:00\n\
+        \x20 TRACK 02 AUDIO\n\
+        \x20   TITLE \"Hidden\"\n\
+        \x20   INDEX 00 09:20:00\n\
+        \x20 TRACK 03 AUDIO\n\
+        \x20   TITLE \"Freddie Freeloader\"\n\
+        \x20   PERFORMER \"Miles Davis Sextet\"\n\
+        \x20   INDEX 00 09:22:00\n\
+        \x20   INDEX 01 09:22:37\n\
+        FILE \"side b.flac\" WAVE\n\
+        \x20 TRACK 04 AUDIO\n\
+        \x20   TITLE \"Blue in Green\"\n\
+        \x20   INDEX 01 00:00:00\n\
+        \x20 TRACK 05 AUDIO\n\
+        \x20   TITLE \"All Blues\"\n\
+        \x20   INDEX 01 05:37:74\n";
+
+    fn parse_sheet() -> Vec<CueTrack> {
+        parse(SHEET, Path::new("/music/Kind of Blue"))
+    }
+
+    #[test]
+    fn tracks_without_index_01_are_skipped() {
+        let numbers: Vec<_> = parse_sheet().iter().map(|t| t.tags.track).collect();
+        assert_eq!(numbers, [Some(1), Some(3), Some(4), Some(5)]);
+    }
+
+    #[test]
+    fn offsets_and_ends() {
+        let tracks = parse_sheet();
+        let side_a = PathBuf::from("/music/Kind of Blue/side a.flac");
+        let side_b = PathBuf::from("/music/Kind of Blue/side b.flac");
+        assert_eq!(
+            tracks.iter().map(|t| &t.file).collect::<Vec<_>>(),
+            [&side_a, &side_a, &side_b, &side_b]
+        );
+        // 75 frames per second.
+        assert_eq!(tracks[1].start, 9.0 * 60.0 + 22.0 + 37.0 / 75.0);
+        assert_eq!(tracks[3].start, 5.0 * 60.0 + 37.0 + 74.0 / 75.0);
+        // Each track ends where the next one in the same file starts, the
+        // last one of a file at its end.
+        assert_eq!(tracks[0].end, Some(tracks[1].start));
+        assert_eq!(tracks[1].end, None);
+        assert_eq!(tracks[2].end, Some(tracks[3].start));
+        assert_eq!(tracks[3].end, None);
+    }
+
+    #[test]
+    fn album_values_are_inherited() {
+        let tracks = parse_sheet();
+        let first = &tracks[0].tags;
+        assert_eq!(first.title.as_deref(), Some("So What"));
+        assert_eq!(first.artist.as_deref(), Some("Miles Davis"));
+        assert_eq!(first.album.as_deref(), Some("Kind of Blue"));
+        assert_eq!(first.year, Some(1959));
+        assert_eq!(first.genre.as_deref(), Some("Jazz"));
+        assert_eq!(tracks[1].tags.artist.as_deref(), Some("Miles Davis Sextet"));
+        assert_eq!(tracks[2].tags.artist.as_deref(), Some("Miles Davis"));
+    }
+
+    #[test]
+    fn tracks_before_any_file_are_skipped() {
+        let tracks = parse("TRACK 01 AUDIO\nINDEX 01 00:00:00\n", Path::new("/m"));
+        assert!(tracks.is_empty());
+    }
+}
@@ -0,0 +1,78 @@
+use std::fmt;
+use std::io;
+
+use crate::mpv::MpvError;
+
+/// Failures scripts may want to tell apart. They travel inside `io::Error`
+/// so everything else can keep returning `io::Result`; `main` turns them
+/// into the exit codes listed in the README.
+#[derive(Debug)]
+pub enum Error {
+    /// Unknown command or bad arguments.
+    Usage(String),
+    /// The configuration could not be loaded.
+    Config(String),
+    /// mpv could not be started or talked to.
+    MpvUnreachable(String),
+    /// fzf is not installed.
+    FzfMissing,
+    /// No playlist, file or track by that name.
+    NotFound(String),
+    /// fzf was left without choosing anything.
+    Cancelled,
+}
+
+impl Error {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Usage(_) => 2,
+            Error::Config(_) => 3,
+            Error::MpvUnreachable(_) => 4,
+            Error::FzfMissing => 5,
+            Error::NotFound(_) => 6,
+            // What shells use for Ctrl-C, and what fzf exits with on Esc.
+            Error::Cancelled => 130,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Usage(msg) | Error::NotFound(msg) => write!(f, "{}", msg),
+            Error::Config(msg) => write!(f, "config: {}", msg),
+            Error::MpvUnreachable(msg) => write!(f, "can't reach mpv: {}", msg),
+            Error::FzfMissing => write!(f, "fzf not found, is it installed?"),
+            Error::Cancelled => write!(f, "nothing selected"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        let kind = match e {
+            Error::Usage(_) => io::ErrorKind::InvalidInput,
+            Error::NotFound(_) => io::ErrorKind::NotFound,
+            _ => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, e)
+    }
+}
+
+/// Exit code for an error that made it to `main`. Errors that aren't ours
+/// are bad input when they say so, and otherwise generic failures.
+pub fn exit_code(e: &io::Error) -> u8 {
+    let inner = e.get_ref();
+    if let Some(e) = inner.and_then(|e| e.downcast_ref::<Error>()) {
+        return e.exit_code();
+    }
+    if let Some(MpvError::Protocol(_)) = inner.and_then(|e| e.downcast_ref::<MpvError>()) {
+        return 4;
+    }
+    match e.kind() {
+        io::ErrorKind::InvalidInput => 2,
+        _ => 1,
+    }
+}
@@ -0,0 +1,558 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::cue::{self, CueTrack};
+use crate::json::Json;
+use crate::library::Track;
+use crate::mpv::Segment;
+use crate::playlist::{Entry, Playlist, resolve_location};
+
+/// Playlist file formats orpheus can import and export.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    M3u,
+    M3u8,
+    Pls,
+    Xspf,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "m3u" => Ok(Format::M3u),
+            "m3u8" => Ok(Format::M3u8),
+            "pls" => Ok(Format::Pls),
+            "xspf" => Ok(Format::Xspf),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown playlist format `{}`", s)),
+        }
+    }
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Format> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+}
+
+/// Reads a playlist in any supported format, resolving relative entries
+/// against the file's directory. CUE sheets can be read but not written.
+pub fn read(path: &Path) -> io::Result<Playlist> {
+    if cue::is_cue_sheet(path) {
+        return Ok(cue_playlist(cue::read(path)?));
+    }
+    let format = Format::from_path(path).ok_or_else(|| {
+        io::Error::other(format!("unsupported playlist format: {}", path.display()))
+    })?;
+    if matches!(format, Format::M3u | Format::M3u8) {
+        return Playlist::read(path);
+    }
+
+    let content = fs::read_to_string(path)?;
+    let base = path.parent().unwrap_or(Path::new("."));
+    let playlist = match format {
+        Format::Pls => parse_pls(&content, base),
+        Format::Xspf => parse_xspf(&content, base),
+        _ => parse_json(&content, base),
+    };
+    playlist.map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), e),
+        )
+    })
+}
+
+/// Renders `playlist` with absolute locations, so the result doesn't
+/// depend on where it gets saved.
+pub fn render(playlist: &Playlist, format: Format) -> String {
+    match format {
+        Format::M3u | Format::M3u8 => {
+            let mut playlist = playlist.clone();
+            for entry in &mut playlist.entries {
+                entry.location = entry.path.to_string_lossy().into_owned();
+            }
+            playlist.to_string()
+        }
+        Format::Pls => render_pls(playlist),
+        Format::Xspf => render_xspf(playlist),
+        Format::Json => format!("{}\n", to_json(playlist)),
+    }
+}
+
+/// Splits an M3U-style "Artist - Title" display title.
+fn split_title(entry: &Entry) -> (Option<String>, Option<String>) {
+    let title = entry.title.clone();
+    match (&entry.artist, &title) {
+        (Some(artist), Some(t)) => {
+            let stripped = t.strip_prefix(&format!("{} - ", artist)).map(String::from);
+            (Some(artist.clone()), stripped.or(title))
+        }
+        (None, Some(t)) => match t.split_once(" - ") {
+            Some((artist, title)) => (Some(artist.to_string()), Some(title.to_string())),
+            None => (None, title),
+        },
+        _ => (entry.artist.clone(), None),
+    }
+}
+
+/// One entry per track of the sheet, playing its part of the image.
+fn cue_playlist(tracks: Vec<CueTrack>) -> Playlist {
+    Playlist {
+        name: tracks.first().and_then(|t| t.tags.album.clone()),
+        entries: tracks
+            .into_iter()
+            .map(|t| Entry {
+                title: join_title(t.tags.artist.as_deref(), t.tags.title.as_deref()),
+                artist: t.tags.artist,
+                album: t.tags.album,
+                duration: t.end.map(|end| end - t.start),
+                segment: Some(Segment {
+                    start: t.start,
+                    end: t.end,
+                }),
+                ..Entry::new(t.file)
+            })
+            .collect(),
+        ..Playlist::default()
+    }
+}
+
+fn join_title(artist: Option<&str>, title: Option<&str>) -> Option<String> {
+    match (artist, title) {
+        (Some(artist), Some(title)) => Some(format!("{} - {}", artist, title)),
+        (None, Some(title)) => Some(title.to_string()),
+        _ => None,
+    }
+}
+
+fn located_entry(location: &str, base: &Path) -> Entry {
+    Entry {
+        location: location.to_string(),
+        ..Entry::new(resolve_location(location, base))
+    }
+}
+
+fn parse_pls(content: &str, base: &Path) -> Result<Playlist, String> {
+    // PLS numbers its keys (File1, Title1, Length1, ...) in any order.
+    let mut entries: HashMap<u32, Entry> = HashMap::new();
+    let mut in_playlist = false;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_playlist = line.eq_ignore_ascii_case("[playlist]");
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        if !in_playlist {
+            continue;
+        }
+
+        let split = key.find(|c: char| c.is_ascii_digit()).unwrap_or(key.len());
+        let (field, index) = key.split_at(split);
+        let Ok(index) = index.parse::<u32>() else {
+            continue;
+        };
+        let entry = entries
+            .entry(index)
+            .or_insert_with(|| Entry::new(PathBuf::new()));
+        match field.to_ascii_lowercase().as_str() {
+            "file" => {
+                entry.location = value.to_string();
+                entry.path = resolve_location(value, base);
+            }
+            "title" => entry.title = Some(value.to_string()),
+            "length" => entry.duration = value.parse().ok().filter(|d: &f64| *d >= 0.0),
+            _ => {}
+        }
+    }
+
+    if entries.is_empty() && !in_playlist {
+        return Err("missing [playlist] section".into());
+    }
+    let mut entries: Vec<_> = entries
+        .into_iter()
+        .filter(|(_, e)| !e.location.is_empty())
+        .collect();
+    entries.sort_by_key(|(index, _)| *index);
+    Ok(Playlist {
+        entries: entries.into_iter().map(|(_, e)| e).collect(),
+        ..Playlist::default()
+    })
+}
+
+fn render_pls(playlist: &Playlist) -> String {
+    let mut out = String::from("[playlist]\n");
+    for (i, entry) in playlist.entries.iter().enumerate() {
+        let n = i + 1;
+        let _ = writeln!(out, "File{}={}", n, entry.path.display());
+        if let Some(title) = &entry.title {
+            let _ = writeln!(out, "Title{}={}", n, title);
+        }
+        let length = entry.duration.map_or(-1, |d| d.round() as i64);
+        let _ = writeln!(out, "Length{}={}", n, length);
+    }
+    let _ = writeln!(out, "NumberOfEntries={}", playlist.entries.len());
+    let _ = writeln!(out, "Version=2");
+    out
+}
+
+/// Text content of the first `<tag>...</tag>` in `xml`.
+fn xml_element<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{}", tag);
+    let mut search = 0;
+    // Skip tags that merely share the prefix, e.g. <title> vs <titles>.
+    let start = loop {
+        let pos = search + xml[search..].find(&open)?;
+        let after = &xml[pos + open.len()..];
+        if after.starts_with('>') || after.starts_with(char::is_whitespace) {
+            break pos + open.len() + after.find('>')? + 1;
+        }
+        search = pos + open.len();
+    };
+    let end = start + xml[start..].find(&format!("</{}>", tag))?;
+    Some(&xml[start..end])
+}
+
+/// Byte offset of `inner`, a slice borrowed from `outer`.
+fn offset_in(outer: &str, inner: &str) -> usize {
+    inner.as_ptr() as usize - outer.as_ptr() as usize
+}
+
+fn parse_xspf(content: &str, base: &Path) -> Result<Playlist, String> {
+    let track_list = xml_element(content, "trackList").ok_or("missing <trackList>")?;
+    let header = &content[..offset_in(content, track_list)];
+
+    let mut playlist = Playlist {
+        name: xml_element(header, "title").map(decode_entities),
+        ..Playlist::default()
+    };
+
+    let mut rest = track_list;
+    while let Some(track) = xml_element(rest, "track") {
+        rest = &rest[offset_in(rest, track) + track.len()..];
+
+        let Some(location) = xml_element(track, "location").map(decode_entities) else {
+            continue;
+        };
+        let artist = xml_element(track, "creator").map(decode_entities);
+        let title = xml_element(track, "title").map(decode_entities);
+        playlist.entries.push(Entry {
+            title: join_title(artist.as_deref(), title.as_deref()),
+            artist,
+            album: xml_element(track, "album").map(decode_entities),
+            duration: xml_element(track, "duration")
+                .and_then(|d| d.trim().parse::<f64>().ok())
+                .map(|ms| ms / 1000.0),
+            ..located_entry(location.trim(), base)
+        });
+    }
+    Ok(playlist)
+}
+
+fn decode_entities(s: &str) -> String {
+    let s = s.trim();
+    if let Some(cdata) = s
+        .strip_prefix("<![CDATA[")
+        .and_then(|s| s.strip_suffix("]]>"))
+    {
+        return cdata.to_string();
+    }
+
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let Some(semi) = rest.find(';') else {
+            break;
+        };
+        let decoded = match &rest[1..semi] {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            e if e.starts_with("#x") => u32::from_str_radix(&e[2..], 16)
+                .ok()
+                .and_then(char::from_u32),
+            e if e.starts_with('#') => e[1..].parse().ok().and_then(char::from_u32),
+            _ => None,
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[semi + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// `file://` URI for a local path, percent-encoding everything but the
+/// unreserved characters and `/`.
+fn file_uri(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    if path.contains("://") {
+        return path.into_owned();
+    }
+    let mut uri = String::from("file://");
+    for &b in path.as_bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(b as char)
+            }
+            _ => {
+                let _ = write!(uri, "%{:02X}", b);
+            }
+        }
+    }
+    uri
+}
+
+fn render_xspf(playlist: &Playlist) -> String {
+    let mut out = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n",
+    );
+    if let Some(name) = &playlist.name {
+        let _ = writeln!(out, "  <title>{}</title>", escape_xml(name));
+    }
+    out.push_str("  <trackList>\n");
+    for entry in &playlist.entries {
+        let (artist, title) = split_title(entry);
+        out.push_str("    <track>\n");
+        let _ = writeln!(
+            out,
+            "      <location>{}</location>",
+            escape_xml(&file_uri(&entry.path))
+        );
+        if let Some(title) = title {
+            let _ = writeln!(out, "      <title>{}</title>", escape_xml(&title));
+        }
+        if let Some(artist) = artist {
+            let _ = writeln!(out, "      <creator>{}</creator>", escape_xml(&artist));
+        }
+        if let Some(album) = &entry.album {
+            let _ = writeln!(out, "      <album>{}</album>", escape_xml(album));
+        }
+        if let Some(duration) = entry.duration {
+            let _ = writeln!(
+                out,
+                "      <duration>{}</duration>",
+                (duration * 1000.0).round() as i64
+            );
+        }
+        out.push_str("    </track>\n");
+    }
+    out.push_str("  </trackList>\n</playlist>\n");
+    out
+}
+
+fn to_json(playlist: &Playlist) -> Json {
+    let tracks = playlist
+        .entries
+        .iter()
+        .map(|entry| {
+            let (artist, title) = split_title(entry);
+            Json::object([
+                ("path", entry.path.to_string_lossy().into_owned().into()),
+                ("title", title.into()),
+                ("artist", artist.into()),
+                ("album", entry.album.clone().into()),
+                ("duration", entry.duration.into()),
+            ])
+        })
+        .collect();
+    Json::object([
+        ("name", playlist.name.clone().into()),
+        ("tracks", Json::Array(tracks)),
+    ])
+}
+
+fn parse_json(content: &str, base: &Path) -> Result<Playlist, String> {
+    let value = Json::parse(content)?;
+    let tracks = value
+        .get("tracks")
+        .and_then(Json::as_array)
+        .ok_or("missing `tracks` array")?;
+
+    let string = |v: &Json, key: &str| v.get(key).and_then(Json::as_str).map(String::from);
+    let entries = tracks
+        .iter()
+        .filter_map(|track| {
+            let location = string(track, "path")?;
+            let artist = string(track, "artist");
+            Some(Entry {
+                title: join_title(artist.as_deref(), string(track, "title").as_deref()),
+                artist,
+                album: string(track, "album"),
+                duration: track.get("duration").and_then(Json::as_f64),
+                ..located_entry(&location, base)
+            })
+        })
+        .collect();
+
+    Ok(Playlist {
+        name: string(&value, "name"),
+        entries,
+        ..Playlist::default()
+    })
+}
+
+/// Result of matching imported entries against the library.
+#[derive(Debug, Default)]
+pub struct Rewrite {
+    pub rewritten: usize,
+    pub missing: Vec<PathBuf>,
+}
+
+/// Points entries that don't exist locally at the library track sharing the
+/// longest path suffix with them, so playlists from other machines (e.g.
+/// `/sdcard/Music/Artist/Album/01.flac`) map onto the local music roots.
+/// At least the parent directory has to match, unless the file name is
+/// unique in the library.
+pub fn rewrite_paths(playlist: &mut Playlist, tracks: &[Track]) -> Rewrite {
+    let mut by_name: HashMap<&std::ffi::OsStr, Vec<&Track>> = HashMap::new();
+    for track in tracks {
+        if let Some(name) = track.path.file_name() {
+            by_name.entry(name).or_default().push(track);
+        }
+    }
+
+    let mut rewrite = Rewrite::default();
+    for entry in &mut playlist.entries {
+        if entry.location.contains("://") && !entry.location.starts_with("file://") {
+            continue;
+        }
+        if entry.path.exists() {
+            continue;
+        }
+        let candidates = entry
+            .path
+            .file_name()
+            .and_then(|name| by_name.get(name))
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        // The longest suffix wins, ties go to the first path in sort order.
+        let best = candidates
+            .iter()
+            .map(|track| (common_suffix(&track.path, &entry.path), *track))
+            .max_by(|(a, x), (b, y)| a.cmp(b).then_with(|| y.path.cmp(&x.path)));
+        // A shared file name alone (`01.flac`) says nothing unless no other
+        // track in the library has it.
+        let unique = candidates.iter().all(|t| t.path == candidates[0].path);
+        let best = best
+            .filter(|(suffix, _)| *suffix >= 2 || unique)
+            .map(|(_, track)| track);
+
+        match best {
+            Some(track) => {
+                entry.path = track.path.clone();
+                entry.location = track.path.to_string_lossy().into_owned();
+                rewrite.rewritten += 1;
+            }
+            None => rewrite.missing.push(entry.path.clone()),
+        }
+    }
+    rewrite
+}
+
+fn common_suffix(a: &Path, b: &Path) -> usize {
+    a.components()
+        .rev()
+        .zip(b.components().rev())
+        .take_while(|(x, y)| x == y)
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tags::Tags;
+
+    fn track(path: &str) -> Track {
+        Track::with_tags(path, Tags::default())
+    }
+
+    fn rewritten(location: &str, tracks: &[Track]) -> (Option<PathBuf>, Rewrite) {
+        let mut playlist = Playlist {
+            entries: vec![Entry::new(PathBuf::from(location))],
+            ..Playlist::default()
+        };
+        let rewrite = rewrite_paths(&mut playlist, tracks);
+        let path = (rewrite.rewritten == 1).then(|| playlist.entries[0].path.clone());
+        (path, rewrite)
+    }
+
+    #[test]
+    fn rewrite_needs_parent_directory() {
+        let tracks = [
+            track("/music/Other Artist/Other Album/01.flac"),
+            track("/music/Someone/Else/01.flac"),
+        ];
+        let (path, rewrite) = rewritten("/sdcard/Coltrane/Blue Train/01.flac", &tracks);
+        assert_eq!(path, None);
+        assert_eq!(rewrite.missing.len(), 1);
+    }
+
+    #[test]
+    fn rewrite_prefers_longest_suffix() {
+        let tracks = [
+            track("/music/Other/Blue Train/01.flac"),
+            track("/music/Coltrane/Blue Train/01.flac"),
+            track("/music/Coltrane/Giant Steps/01.flac"),
+        ];
+        let (path, _) = rewritten("/sdcard/Coltrane/Blue Train/01.flac", &tracks);
+        assert_eq!(
+            path,
+            Some(PathBuf::from("/music/Coltrane/Blue Train/01.flac"))
+        );
+    }
+
+    #[test]
+    fn rewrite_breaks_ties_by_path() {
+        let tracks = [
+            track("/music/b/Blue Train/01.flac"),
+            track("/music/a/Blue Train/01.flac"),
+        ];
+        let (path, _) = rewritten("/sdcard/Blue Train/01.flac", &tracks);
+        assert_eq!(path, Some(PathBuf::from("/music/a/Blue Train/01.flac")));
+    }
+
+    #[test]
+    fn rewrite_accepts_unique_file_name() {
+        let tracks = [
+            track("/music/Coltrane/Blue Train/Moment's Notice.flac"),
+            track("/music/Other/01.flac"),
+        ];
+        let (path, _) = rewritten("/sdcard/Moment's Notice.flac", &tracks);
+        assert_eq!(
+            path,
+            Some(PathBuf::from(
+                "/music/Coltrane/Blue Train/Moment's Notice.flac"
+            ))
+        );
+    }
+}
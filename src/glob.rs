@@ -0,0 +1,93 @@
+/// Gitignore-style matching of `pattern` against `path`, a `/`-separated
+/// path relative to a music root.
+///
+/// * A pattern without `/` matches any single component, so `*.part` or
+///   `Samples` apply at every depth.
+/// * Otherwise it is anchored at the root; `**` matches any number of
+///   components, `*` and `?` match within one component and `[a-z]` /
+///   `[!a-z]` match character classes.
+/// * A trailing `/` only matches directories.
+pub fn matches(pattern: &str, path: &str, is_dir: bool) -> bool {
+    let (pattern, dir_only) = match pattern.strip_suffix('/') {
+        Some(p) => (p, true),
+        None => (pattern, false),
+    };
+    if dir_only && !is_dir {
+        return false;
+    }
+
+    let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+    if !pattern.contains('/') {
+        return components.iter().any(|c| match_component(pattern, c));
+    }
+
+    let segments: Vec<&str> = pattern
+        .trim_start_matches('/')
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect();
+    match_segments(&segments, &components)
+        // `dir/**` should prune `dir` itself during the walk.
+        || (is_dir
+            && segments.last() == Some(&"**")
+            && match_segments(&segments[..segments.len() - 1], &components))
+}
+
+fn match_segments(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|i| match_segments(rest, &path[i..])),
+        Some((segment, rest)) => match path.split_first() {
+            Some((component, path)) => {
+                match_component(segment, component) && match_segments(rest, path)
+            }
+            None => false,
+        },
+    }
+}
+
+fn match_component(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    match_chars(&pattern, &text)
+}
+
+fn match_chars(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') => (0..=text.len()).any(|i| match_chars(&pattern[1..], &text[i..])),
+        Some('?') => !text.is_empty() && match_chars(&pattern[1..], &text[1..]),
+        Some('[') => {
+            let Some(end) = pattern.iter().skip(1).position(|&c| c == ']') else {
+                // Unterminated class, treat `[` literally.
+                return text.first() == Some(&'[') && match_chars(&pattern[1..], &text[1..]);
+            };
+            let class = &pattern[1..end + 1];
+            match text.first() {
+                Some(&c) => match_class(class, c) && match_chars(&pattern[end + 2..], &text[1..]),
+                None => false,
+            }
+        }
+        Some(&c) => text.first() == Some(&c) && match_chars(&pattern[1..], &text[1..]),
+    }
+}
+
+fn match_class(class: &[char], c: char) -> bool {
+    let (negated, class) = match class.first() {
+        Some('!' | '^') => (true, &class[1..]),
+        _ => (false, class),
+    };
+
+    let mut matched = false;
+    let mut i = 0;
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == '-' {
+            matched |= (class[i]..=class[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            matched |= class[i] == c;
+            i += 1;
+        }
+    }
+    matched != negated
+}
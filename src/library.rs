@@ -0,0 +1,661 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::data_dir;
+use crate::cue;
+use crate::json::Json;
+use crate::mpv::Segment;
+use crate::playlist::scan_music;
+use crate::tags::{self, Tags};
+use crate::walk::MusicFile;
+
+const INDEX_VERSION: i64 = 4;
+
+/// A track in the library index.
+#[derive(Debug, Clone)]
+pub struct Track {
+    pub path: PathBuf,
+    /// The music root the track was found under.
+    pub root: PathBuf,
+    /// Modification time in seconds since the epoch, used to detect changes.
+    pub mtime: u64,
+    pub size: u64,
+    /// When the track was first indexed, in seconds since the epoch.
+    pub added: u64,
+    pub duration: Option<f64>,
+    pub tags: Tags,
+    /// For tracks split out of an image by a CUE sheet: the sheet, which
+    /// `mtime` and `size` then refer to.
+    pub sheet: Option<PathBuf>,
+    /// For CUE tracks: the part of `path` making up the track.
+    pub segment: Option<Segment>,
+}
+
+impl Track {
+    /// A track under `/music` that was never on disk, for tests.
+    #[cfg(test)]
+    pub fn with_tags(path: &str, tags: Tags) -> Track {
+        Track {
+            path: PathBuf::from(path),
+            root: PathBuf::from("/music"),
+            mtime: 0,
+            size: 0,
+            added: 0,
+            duration: None,
+            tags,
+            sheet: None,
+            segment: None,
+        }
+    }
+
+    /// Indexes `file`: a single track for audio files, one per `TRACK` for
+    /// CUE sheets.
+    fn read(file: &MusicFile, mtime: u64, size: u64, added: u64) -> Vec<Track> {
+        if cue::is_cue_sheet(&file.path) {
+            return Track::read_cue(file, mtime, size, added);
+        }
+
+        // An unreadable file still belongs in the library, just without tags.
+        let info = tags::read(&file.path).unwrap_or_default();
+        vec![Track {
+            path: file.path.clone(),
+            root: file.root.clone(),
+            mtime,
+            size,
+            added,
+            duration: info.duration,
+            tags: info.tags,
+            sheet: None,
+            segment: None,
+        }]
+    }
+
+    fn read_cue(sheet: &MusicFile, mtime: u64, size: u64, added: u64) -> Vec<Track> {
+        let Ok(cue_tracks) = cue::read(&sheet.path) else {
+            return Vec::new();
+        };
+
+        let mut images: HashMap<PathBuf, tags::Info> = HashMap::new();
+        let mut tracks = Vec::new();
+        for cue_track in cue_tracks {
+            if !cue_track.file.is_file() {
+                continue;
+            }
+            let image = images
+                .entry(cue_track.file.clone())
+                .or_insert_with(|| tags::read(&cue_track.file).unwrap_or_default());
+
+            // The sheet wins, the image's own tags fill the gaps.
+            let mut tags = cue_track.tags;
+            tags.artist = tags.artist.or_else(|| image.tags.artist.clone());
+            tags.album = tags.album.or_else(|| image.tags.album.clone());
+            tags.year = tags.year.or(image.tags.year);
+            tags.genre = tags.genre.or_else(|| image.tags.genre.clone());
+
+            let end = cue_track.end.or(image.duration);
+            tracks.push(Track {
+                path: cue_track.file,
+                root: sheet.root.clone(),
+                mtime,
+                size,
+                added,
+                duration: end.map(|end| end - cue_track.start),
+                tags,
+                sheet: Some(sheet.path.clone()),
+                segment: Some(Segment {
+                    start: cue_track.start,
+                    end: cue_track.end,
+                }),
+            });
+        }
+        tracks
+    }
+
+    pub fn start(&self) -> f64 {
+        self.segment.map_or(0.0, |s| s.start)
+    }
+
+    /// The file whose mtime and size the track was indexed with.
+    fn source(&self) -> &Path {
+        self.sheet.as_deref().unwrap_or(&self.path)
+    }
+
+    /// Human readable name for pickers: "Artist - Title (Album)", falling
+    /// back to the path relative to its music root.
+    pub fn label(&self) -> String {
+        let Some(title) = &self.tags.title else {
+            let relative = self.path.strip_prefix(&self.root).unwrap_or(&self.path);
+            return relative.display().to_string();
+        };
+
+        let mut label = match &self.tags.artist {
+            Some(artist) => format!("{} - {}", artist, title),
+            None => title.clone(),
+        };
+        if let Some(album) = &self.tags.album {
+            label.push_str(&format!(" ({})", album));
+        }
+        label
+    }
+
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("path", self.path.to_string_lossy().into_owned().into()),
+            ("root", self.root.to_string_lossy().into_owned().into()),
+            ("mtime", (self.mtime as i64).into()),
+            ("size", (self.size as i64).into()),
+            ("added", (self.added as i64).into()),
+            ("duration", self.duration.into()),
+            ("artist", self.tags.artist.clone().into()),
+            ("album", self.tags.album.clone().into()),
+            ("title", self.tags.title.clone().into()),
+            ("track", self.tags.track.map(i64::from).into()),
+            ("year", self.tags.year.map(i64::from).into()),
+            ("genre", self.tags.genre.clone().into()),
+            ("rating", self.tags.rating.into()),
+            ("play_count", self.tags.play_count.map(i64::from).into()),
+            (
+                "sheet",
+                self.sheet
+                    .as_ref()
+                    .map(|s| s.to_string_lossy().into_owned())
+                    .into(),
+            ),
+            ("start", self.segment.map(|s| s.start).into()),
+            ("end", self.segment.and_then(|s| s.end).into()),
+        ])
+    }
+
+    fn from_json(value: &Json) -> Option<Track> {
+        let string = |key: &str| value.get(key).and_then(Json::as_str).map(String::from);
+        let number = |key: &str| value.get(key).and_then(Json::as_i64);
+
+        Some(Track {
+            path: PathBuf::from(value.get("path")?.as_str()?),
+            root: PathBuf::from(value.get("root")?.as_str()?),
+            mtime: number("mtime")? as u64,
+            size: number("size")? as u64,
+            added: number("added")? as u64,
+            duration: value.get("duration").and_then(Json::as_f64),
+            tags: Tags {
+                artist: string("artist"),
+                album: string("album"),
+                title: string("title"),
+                track: number("track").map(|n| n as u32),
+                year: number("year").map(|n| n as i32),
+                genre: string("genre"),
+                rating: value.get("rating").and_then(Json::as_f64),
+                play_count: number("play_count").map(|n| n as u32),
+            },
+            sheet: string("sheet").map(PathBuf::from),
+            segment: value
+                .get("start")
+                .and_then(Json::as_f64)
+                .map(|start| Segment {
+                    start,
+                    end: value.get("end").and_then(Json::as_f64),
+                }),
+        })
+    }
+}
+
+/// Differences found by `Library::update`.
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    pub added: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+    pub changed: Vec<PathBuf>,
+}
+
+impl ScanReport {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Persistent index of every track under the music roots, stored as JSON lines
+/// in the data dir so that tags only have to be read once per file.
+#[derive(Debug, Default)]
+pub struct Library {
+    pub tracks: Vec<Track>,
+}
+
+fn index_path() -> PathBuf {
+    data_dir().join("library.jsonl")
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn file_stamp(path: &Path) -> io::Result<(u64, u64)> {
+    let meta = fs::metadata(path)?;
+    let mtime = meta
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    Ok((mtime, meta.len()))
+}
+
+impl Library {
+    /// Loads the index, or an empty library if there is none yet. An index
+    /// written by a different version is discarded and rebuilt.
+    pub fn load() -> io::Result<Library> {
+        let file = match File::open(index_path()) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Library::default()),
+            Err(e) => return Err(e),
+        };
+
+        let mut lines = BufReader::new(file).lines();
+        let version = match lines.next() {
+            Some(line) => Json::parse(&line?)
+                .ok()
+                .and_then(|h| h.get("version").and_then(Json::as_i64)),
+            None => None,
+        };
+        if version != Some(INDEX_VERSION) {
+            return Ok(Library::default());
+        }
+
+        let mut tracks = Vec::new();
+        for line in lines {
+            if let Some(track) = Json::parse(&line?).ok().as_ref().and_then(Track::from_json) {
+                tracks.push(track);
+            }
+        }
+        Ok(Library { tracks })
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = index_path();
+        let tmp = path.with_extension("jsonl.tmp");
+
+        let mut file = BufWriter::new(File::create(&tmp)?);
+        writeln!(
+            file,
+            "{}",
+            Json::object([("version", INDEX_VERSION.into())])
+        )?;
+        for track in &self.tracks {
+            writeln!(file, "{}", track.to_json())?;
+        }
+        file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+
+        // Rename so an interrupted save never leaves a truncated index.
+        fs::rename(tmp, path)
+    }
+
+    /// Walks the music roots and re-reads tags only for files that are new or
+    /// whose mtime or size changed since the last update. With `full`, tags
+    /// of every file are re-read.
+    pub fn update(&mut self, full: bool) -> io::Result<ScanReport> {
+        let mut known: HashMap<PathBuf, Vec<Track>> = HashMap::new();
+        for track in self.tracks.drain(..) {
+            known
+                .entry(track.source().to_path_buf())
+                .or_default()
+                .push(track);
+        }
+        let mut report = ScanReport::default();
+
+        let now = now();
+        let mut seen = HashSet::new();
+        let walk = scan_music()?;
+        // Sheets go first so the images they split up are known before the
+        // audio files are visited.
+        let (sheets, files): (Vec<_>, Vec<_>) = walk
+            .files
+            .into_iter()
+            .partition(|f| cue::is_cue_sheet(&f.path));
+        let mut covered = HashSet::new();
+
+        for file in sheets.into_iter().chain(files) {
+            // Nested roots would otherwise index the same file twice.
+            if !seen.insert(file.path.clone()) || covered.contains(&file.path) {
+                continue;
+            }
+            let Ok((mtime, size)) = file_stamp(&file.path) else {
+                continue;
+            };
+
+            let tracks = match known.remove(&file.path) {
+                Some(tracks) if tracks[0].mtime == mtime && tracks[0].size == size => {
+                    if full {
+                        Track::read(&file, mtime, size, tracks[0].added)
+                    } else {
+                        tracks
+                            .into_iter()
+                            .map(|track| Track {
+                                root: file.root.clone(),
+                                ..track
+                            })
+                            .collect()
+                    }
+                }
+                Some(tracks) => {
+                    report.changed.push(file.path.clone());
+                    Track::read(&file, mtime, size, tracks[0].added)
+                }
+                None => {
+                    report.added.push(file.path.clone());
+                    Track::read(&file, mtime, size, now)
+                }
+            };
+            covered.extend(
+                tracks
+                    .iter()
+                    .filter(|t| t.sheet.is_some())
+                    .map(|t| t.path.clone()),
+            );
+            self.tracks.extend(tracks);
+        }
+
+        // Keep tracks of roots that are offline rather than dropping them.
+        for (path, tracks) in known {
+            if walk.unreachable_roots.contains(&tracks[0].root) {
+                self.tracks.extend(tracks);
+            } else {
+                report.removed.push(path);
+            }
+        }
+        report.added.sort();
+        report.removed.sort();
+        report.changed.sort();
+        self.tracks
+            .sort_by(|a, b| a.path.cmp(&b.path).then(a.start().total_cmp(&b.start())));
+        Ok(report)
+    }
+}
+
+/// Returns the indexed library. The music directory is only walked when
+/// there is no index yet; `orpheus scan` refreshes it explicitly.
+pub fn tracks() -> io::Result<Vec<Track>> {
+    if index_path().exists() {
+        let library = Library::load()?;
+        if !library.tracks.is_empty() {
+            return Ok(library.tracks);
+        }
+    }
+
+    let mut library = Library::default();
+    library.update(false)?;
+    library.save()?;
+    Ok(library.tracks)
+}
+
+/// Refreshes the index and reports what changed. With `dry_run` the index
+/// on disk is left untouched.
+pub fn scan(full: bool, dry_run: bool) -> io::Result<ScanReport> {
+    let mut library = Library::load()?;
+    let report = library.update(full)?;
+    if !dry_run {
+        library.save()?;
+    }
+    Ok(report)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Grouping {
+    Album,
+    Artist,
+}
+
+/// Tracks played as a unit, in playing order.
+pub struct Group {
+    pub label: String,
+    pub tracks: Vec<Track>,
+}
+
+/// The directory holding an album, skipping `CD1`/`Disc 2` style folders.
+fn album_dir(track: &Track) -> &Path {
+    album_dir_of(&track.path)
+}
+
+/// The directory of the album a file belongs to: its parent, or the one
+/// above for `CD1`/`Disc 2` folders.
+pub fn album_dir_of(path: &Path) -> &Path {
+    let parent = path.parent().unwrap_or(path);
+    let is_disc = parent.file_name().is_some_and(|name| {
+        let name = name.to_string_lossy().to_lowercase();
+        ["cd", "disc", "disk"].iter().any(|prefix| {
+            name.strip_prefix(prefix)
+                .is_some_and(|n| n.trim().parse::<u32>().is_ok())
+        })
+    });
+    match parent.parent() {
+        Some(grandparent) if is_disc => grandparent,
+        _ => parent,
+    }
+}
+
+/// The first directory below the music root, for `Artist/Album/Track` layouts.
+fn artist_dir(track: &Track) -> String {
+    let relative = track.path.strip_prefix(&track.root).unwrap_or(&track.path);
+    let mut components = relative.components();
+    match (components.next(), components.next()) {
+        (Some(first), Some(_)) => first.as_os_str().to_string_lossy().into_owned(),
+        _ => "Unknown artist".to_string(),
+    }
+}
+
+fn album_label(tracks: &[Track]) -> String {
+    let first = &tracks[0];
+    let Some(album) = &first.tags.album else {
+        let dir = album_dir(first);
+        return dir
+            .strip_prefix(&first.root)
+            .unwrap_or(dir)
+            .display()
+            .to_string();
+    };
+    let artist = match &first.tags.artist {
+        Some(artist)
+            if tracks
+                .iter()
+                .all(|t| t.tags.artist.as_ref() == Some(artist)) =>
+        {
+            artist
+        }
+        _ => "Various Artists",
+    };
+    match first.tags.year {
+        Some(year) => format!("{} - {} ({})", artist, album, year),
+        None => format!("{} - {}", artist, album),
+    }
+}
+
+/// Groups the library by album or artist. Tracks without the tag are grouped
+/// by directory instead. Albums play in track number order, artists album by
+/// album in release order.
+pub fn group(tracks: &[Track], grouping: Grouping) -> Vec<Group> {
+    let mut groups: HashMap<(String, PathBuf), Vec<Track>> = HashMap::new();
+    for track in tracks {
+        let key = match grouping {
+            Grouping::Album => (
+                track.tags.album.clone().unwrap_or_default().to_lowercase(),
+                album_dir(track).to_path_buf(),
+            ),
+            Grouping::Artist => match &track.tags.artist {
+                Some(artist) => (artist.to_lowercase(), PathBuf::new()),
+                None => (artist_dir(track).to_lowercase(), track.root.clone()),
+            },
+        };
+        groups.entry(key).or_default().push(track.clone());
+    }
+
+    let mut groups: Vec<Group> = groups
+        .into_values()
+        .map(|mut tracks| {
+            tracks.sort_by(|a, b| {
+                let year = |t: &Track| t.tags.year.unwrap_or(i32::MAX);
+                let track_number = |t: &Track| t.tags.track.unwrap_or(u32::MAX);
+                let release = match grouping {
+                    Grouping::Album => Ordering::Equal,
+                    Grouping::Artist => year(a)
+                        .cmp(&year(b))
+                        .then_with(|| album_dir(a).cmp(album_dir(b))),
+                };
+                release
+                    .then_with(|| a.path.parent().cmp(&b.path.parent()))
+                    .then_with(|| track_number(a).cmp(&track_number(b)))
+                    .then_with(|| a.path.cmp(&b.path))
+                    .then_with(|| a.start().total_cmp(&b.start()))
+            });
+            let label = match grouping {
+                Grouping::Album => album_label(&tracks),
+                Grouping::Artist => {
+                    let name = tracks[0]
+                        .tags
+                        .artist
+                        .clone()
+                        .unwrap_or_else(|| artist_dir(&tracks[0]));
+                    match tracks.len() {
+                        1 => format!("{} (1 track)", name),
+                        n => format!("{} ({} tracks)", name, n),
+                    }
+                }
+            };
+            Group { label, tracks }
+        })
+        .collect();
+    groups.sort_by_key(|g| g.label.to_lowercase());
+    groups
+}
+
+/// Compares paths component by component, with runs of digits compared by
+/// value, so "Track 2" sorts before "Track 10".
+pub fn natural_cmp(a: &Path, b: &Path) -> Ordering {
+    let mut a_components = a.components();
+    let mut b_components = b.components();
+    loop {
+        match (a_components.next(), b_components.next()) {
+            (Some(a), Some(b)) => {
+                let ordering = natural_cmp_str(
+                    &a.as_os_str().to_string_lossy(),
+                    &b.as_os_str().to_string_lossy(),
+                );
+                if ordering.is_ne() {
+                    return ordering;
+                }
+            }
+            (a, b) => return a.is_some().cmp(&b.is_some()),
+        }
+    }
+}
+
+fn natural_cmp_str(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let digits = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+                    let mut run = String::new();
+                    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+                        run.push(c);
+                    }
+                    run.trim_start_matches('0').to_string()
+                };
+                let (x, y) = (digits(&mut a_chars), digits(&mut b_chars));
+                let ordering = x.len().cmp(&y.len()).then_with(|| x.cmp(&y));
+                if ordering.is_ne() {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                if ordering.is_ne() {
+                    return ordering;
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+            // Equal apart from case or leading zeros: fall back to plain order.
+            (None, None) => return a.cmp(b),
+            (x, y) => return x.is_some().cmp(&y.is_some()),
+        }
+    }
+}
+
+/// The tracks below `dir`, in natural path order.
+pub fn tracks_under<'a>(tracks: &'a [Track], dir: &Path) -> Vec<&'a Track> {
+    let mut below: Vec<_> = tracks.iter().filter(|t| t.path.starts_with(dir)).collect();
+    below.sort_by(|a, b| {
+        natural_cmp(&a.path, &b.path).then_with(|| a.start().total_cmp(&b.start()))
+    });
+    below
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(paths: &[&str]) -> Vec<String> {
+        let mut paths: Vec<_> = paths.iter().map(PathBuf::from).collect();
+        paths.sort_by(|a, b| natural_cmp(a, b));
+        paths
+            .iter()
+            .map(|p| p.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn numbers_compare_by_value() {
+        assert_eq!(
+            sorted(&["Track 10.flac", "Track 2.flac", "Track 1.flac"]),
+            ["Track 1.flac", "Track 2.flac", "Track 10.flac"]
+        );
+        assert_eq!(
+            sorted(&["Disc 10/01.flac", "Disc 2/02.flac", "Disc 2/10.flac"]),
+            ["Disc 2/02.flac", "Disc 2/10.flac", "Disc 10/01.flac"]
+        );
+    }
+
+    #[test]
+    fn leading_zeros() {
+        assert_eq!(
+            sorted(&["02.flac", "1.flac", "010.flac"]),
+            ["1.flac", "02.flac", "010.flac"]
+        );
+        // Same value: zero-padded first, so the order is still total.
+        assert_eq!(
+            natural_cmp(Path::new("1.flac"), Path::new("01.flac")),
+            Ordering::Greater
+        );
+        assert_eq!(
+            natural_cmp(Path::new("01.flac"), Path::new("01.flac")),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn case_is_ignored_until_everything_else_is_equal() {
+        assert_eq!(
+            sorted(&["banana", "Apple", "cherry"]),
+            ["Apple", "banana", "cherry"]
+        );
+        assert_eq!(sorted(&["track", "Track"]), ["Track", "track"]);
+    }
+
+    #[test]
+    fn paths_compare_by_component() {
+        // A plain string comparison would put "a b" first, as ' ' < '/'.
+        assert_eq!(
+            sorted(&["/m/a b/1.flac", "/m/a/1.flac"]),
+            ["/m/a/1.flac", "/m/a b/1.flac"]
+        );
+        assert_eq!(
+            sorted(&["/m/Album/CD1/01.flac", "/m/Album", "/m/Album/01.flac"]),
+            ["/m/Album", "/m/Album/01.flac", "/m/Album/CD1/01.flac"]
+        );
+    }
+}
@@ -1,95 +1,554 @@
+mod complete;
 mod config;
+mod cue;
+mod error;
+mod formats;
+mod glob;
+mod json;
+mod library;
 mod mpv;
 mod playlist;
+mod query;
+mod regex;
+mod session;
+mod shuffle;
+mod tags;
 mod ui;
+mod walk;
 
+use formats::Format;
+use library::Grouping;
 use mpv::*;
-use playlist::{edit_playlist, list_playlists, scan_music};
-use std::{env, path::PathBuf};
-use ui::run_fzf;
+use playlist::{Entry, edit_playlist, list_playlists, play_playlist};
+use std::{env, path::PathBuf, process::ExitCode};
+use ui::{run_fzf, select_tracks};
 
+use crate::error::Error;
 use crate::playlist::{create_playlist, delete_playlists, jump};
+use crate::session::Session;
+use crate::shuffle::Shuffle;
 
 #[derive(Debug)]
 enum Command {
     List,
-    Create { name: String },
-    Edit,
-    Delete,
-    Play,
-    Append,
+    Create {
+        name: String,
+        paths: Vec<String>,
+        smart: Option<String>,
+        query: Option<String>,
+    },
+    Playlist {
+        action: PlaylistAction,
+    },
+    Edit {
+        name: Option<String>,
+    },
+    Delete {
+        names: Vec<String>,
+    },
+    Play {
+        query: Option<String>,
+        targets: Vec<String>,
+        browse: Option<Browse>,
+        shuffle: Option<Shuffle>,
+    },
+    Append {
+        query: Option<String>,
+        targets: Vec<String>,
+        browse: Option<Browse>,
+        /// Insert after the current track instead of at the end.
+        next: bool,
+        shuffle: Option<Shuffle>,
+    },
     Reload,
     Jump,
-    Shuffle { enabled: bool },
+    Queue {
+        action: QueueAction,
+    },
+    Session {
+        restore: bool,
+    },
+    Shuffle {
+        enabled: bool,
+    },
+    Repeat {
+        mode: Option<Repeat>,
+    },
+    Status {
+        json: bool,
+    },
+    Pause,
+    Resume,
+    Toggle,
+    Next,
+    Prev,
+    Stop,
+    Seek {
+        target: SeekTarget,
+    },
+    Scan {
+        full: bool,
+        dry_run: bool,
+    },
+    Import {
+        file: PathBuf,
+        name: Option<String>,
+    },
+    Export {
+        name: String,
+        format: Option<Format>,
+        output: Option<PathBuf>,
+    },
+    Volume {
+        change: Option<VolumeChange>,
+    },
+    Mute {
+        muted: Option<MuteChange>,
+    },
     Help,
 }
 
+/// Units `play` and `append` can pick in fzf instead of single tracks.
+#[derive(Debug)]
+enum Browse {
+    Album,
+    Artist,
+    Folder,
+}
+
+#[derive(Debug)]
+enum PlaylistAction {
+    Add { name: String, paths: Vec<String> },
+    Remove { name: String, pattern: String },
+    Show { name: String },
+    Rename { old: String, new: String },
+    Copy { from: String, to: String },
+}
+
+/// Positions are 1-based, as shown by `queue list`.
+#[derive(Debug)]
+enum QueueAction {
+    List,
+    Remove { positions: Vec<usize> },
+    Move { from: usize, to: usize },
+    Clear { keep_current: bool },
+    Save { name: String },
+    Shuffle,
+}
+
+#[derive(Debug)]
+enum MuteChange {
+    On,
+    Off,
+    Toggle,
+}
+
 impl Command {
     fn all() -> &'static [&'static str] {
         &[
-            "list", "create", "edit", "delete", "play", "append", "reload", "jump", "help",
+            "list", "create", "playlist", "edit", "delete", "play", "append", "playnext", "reload",
+            "jump", "queue", "session", "shuffle", "repeat", "status", "pause", "resume", "toggle",
+            "next", "prev", "stop", "seek", "volume", "mute", "scan", "rescan", "import", "export",
+            "help",
         ]
     }
 
+    /// Whether the command talks to mpv, so mpv has to be running first.
+    /// `status` only reports on a running mpv, as status bars poll it.
+    fn needs_mpv(&self) -> bool {
+        !matches!(
+            self,
+            Command::List
+                | Command::Status { .. }
+                | Command::Create { .. }
+                | Command::Playlist { .. }
+                | Command::Edit { .. }
+                | Command::Delete { .. }
+                | Command::Scan { .. }
+                | Command::Import { .. }
+                | Command::Export { .. }
+                | Command::Help
+        )
+    }
+
     fn parse(args: &[String]) -> Option<Command> {
-        match args.get(0).map(|s| s.as_str()) {
+        match args.first().map(|s| s.as_str()) {
             Some("list") => Some(Command::List),
-            Some("create") => args
-                .get(1)
-                .map(|name| Command::Create { name: name.clone() }),
-            Some("edit") => Some(Command::Edit),
-            Some("delete") => Some(Command::Delete),
-            Some("play") => Some(Command::Play),
-            Some("append") => Some(Command::Append),
+            Some("create") => {
+                let name = args.get(1)?.clone();
+                if args.get(2).is_some_and(|a| a == "--smart") {
+                    if args.len() < 4 {
+                        return None;
+                    }
+                    return Some(Command::Create {
+                        name,
+                        paths: Vec::new(),
+                        smart: Some(args[3..].join(" ")),
+                        query: None,
+                    });
+                }
+                let query = query_arg(&args[2..])?;
+                Some(Command::Create {
+                    name,
+                    paths: if query.is_some() {
+                        Vec::new()
+                    } else {
+                        args[2..].to_vec()
+                    },
+                    smart: None,
+                    query,
+                })
+            }
+            Some("playlist") => {
+                let arg = |i: usize| args.get(i).cloned();
+                let action = match args.get(1)?.as_str() {
+                    "add" if args.len() > 3 => PlaylistAction::Add {
+                        name: arg(2)?,
+                        paths: args[3..].to_vec(),
+                    },
+                    "remove" => PlaylistAction::Remove {
+                        name: arg(2)?,
+                        pattern: arg(3)?,
+                    },
+                    "show" => PlaylistAction::Show { name: arg(2)? },
+                    "rename" => PlaylistAction::Rename {
+                        old: arg(2)?,
+                        new: arg(3)?,
+                    },
+                    "copy" => PlaylistAction::Copy {
+                        from: arg(2)?,
+                        to: arg(3)?,
+                    },
+                    _ => return None,
+                };
+                Some(Command::Playlist { action })
+            }
+            Some("edit") if args.len() <= 2 => Some(Command::Edit {
+                name: args.get(1).cloned(),
+            }),
+            Some("delete") => Some(Command::Delete {
+                names: args[1..].to_vec(),
+            }),
+            Some("play") => {
+                let mut args = args[1..].to_vec();
+                let shuffle = shuffle_arg(&mut args)?;
+                let query = query_arg(&args)?;
+                Some(Command::Play {
+                    browse: browse_arg(&args),
+                    targets: targets_arg(&args, &query),
+                    query,
+                    shuffle,
+                })
+            }
+            Some(command @ ("append" | "playnext")) => {
+                let next = command == "playnext" || args[1..].iter().any(|a| a == "--next");
+                let mut args: Vec<_> = args[1..]
+                    .iter()
+                    .filter(|a| *a != "--next")
+                    .cloned()
+                    .collect();
+                let shuffle = shuffle_arg(&mut args)?;
+                let query = query_arg(&args)?;
+                Some(Command::Append {
+                    browse: browse_arg(&args),
+                    targets: targets_arg(&args, &query),
+                    query,
+                    next,
+                    shuffle,
+                })
+            }
             Some("reload") => Some(Command::Reload),
             Some("jump") => Some(Command::Jump),
+            Some("queue") => {
+                let position = |i: usize| args.get(i)?.parse::<usize>().ok();
+                let action = match args.get(1).map(|s| s.as_str()) {
+                    None | Some("list") => QueueAction::List,
+                    Some("remove") => QueueAction::Remove {
+                        positions: args[2..]
+                            .iter()
+                            .map(|a| a.parse().ok())
+                            .collect::<Option<_>>()?,
+                    },
+                    Some("move") if args.len() == 4 => QueueAction::Move {
+                        from: position(2)?,
+                        to: position(3)?,
+                    },
+                    Some("clear") => QueueAction::Clear {
+                        keep_current: match args.get(2).map(|s| s.as_str()) {
+                            None => false,
+                            Some("--keep-current") => true,
+                            Some(_) => return None,
+                        },
+                    },
+                    Some("save") => QueueAction::Save {
+                        name: args.get(2)?.clone(),
+                    },
+                    Some("shuffle") => QueueAction::Shuffle,
+                    Some(_) => return None,
+                };
+                Some(Command::Queue { action })
+            }
+            Some("session") => Some(Command::Session {
+                restore: match args.get(1)?.as_str() {
+                    "save" => false,
+                    "restore" => true,
+                    _ => return None,
+                },
+            }),
+            Some("status") => Some(Command::Status {
+                json: args[1..].iter().any(|a| a == "--json"),
+            }),
+            Some("pause") => Some(Command::Pause),
+            Some("resume") => Some(Command::Resume),
+            Some("toggle") => Some(Command::Toggle),
+            Some("next") => Some(Command::Next),
+            Some("prev") => Some(Command::Prev),
+            Some("stop") => Some(Command::Stop),
+            Some("seek") => args
+                .get(1)
+                .and_then(|t| t.parse().ok())
+                .map(|target| Command::Seek { target }),
+            Some(cmd @ ("scan" | "rescan")) => Some(Command::Scan {
+                full: cmd == "rescan" || args[1..].iter().any(|a| a == "--full"),
+                dry_run: args[1..].iter().any(|a| a == "--dry-run"),
+            }),
+            Some("import") => args.get(1).map(|file| Command::Import {
+                file: PathBuf::from(file),
+                name: args.get(2).cloned(),
+            }),
+            Some("export") => {
+                let name = args.get(1)?.clone();
+                let mut format = None;
+                let mut output = None;
+                let mut rest = args[2..].iter();
+                while let Some(arg) = rest.next() {
+                    match arg.as_str() {
+                        "--format" | "-f" => format = Some(rest.next()?.parse().ok()?),
+                        "--output" | "-o" => output = Some(PathBuf::from(rest.next()?)),
+                        _ => return None,
+                    }
+                }
+                Some(Command::Export {
+                    name,
+                    format,
+                    output,
+                })
+            }
+            Some("volume") => match args.get(1) {
+                None => Some(Command::Volume { change: None }),
+                Some(v) => v.parse().ok().map(|change| Command::Volume {
+                    change: Some(change),
+                }),
+            },
+            Some("mute") => match args.get(1).map(|s| s.as_str()) {
+                None => Some(Command::Mute { muted: None }),
+                Some("on") => Some(Command::Mute {
+                    muted: Some(MuteChange::On),
+                }),
+                Some("off") => Some(Command::Mute {
+                    muted: Some(MuteChange::Off),
+                }),
+                Some("toggle") => Some(Command::Mute {
+                    muted: Some(MuteChange::Toggle),
+                }),
+                Some(_) => None,
+            },
+            Some("repeat") => match args.get(1) {
+                None => Some(Command::Repeat { mode: None }),
+                Some(mode) => mode
+                    .parse()
+                    .ok()
+                    .map(|mode| Command::Repeat { mode: Some(mode) }),
+            },
             Some("help") => Some(Command::Help),
-            Some("shuffle") => args.get(1).map(|enabled| Command::Shuffle {
-                enabled: enabled.parse().expect(&format!(
-                    "wrong shuffle arg {enabled}\nUsage: orpheus shuffle <true|false>"
-                )),
+            Some("shuffle") => Some(Command::Shuffle {
+                enabled: args.get(1)?.parse().ok()?,
             }),
             _ => None,
         }
     }
 }
 
+/// `Some(None)` without `--query`, `None` if its value is missing.
+fn query_arg(args: &[String]) -> Option<Option<String>> {
+    match args.iter().position(|a| a == "--query" || a == "-q") {
+        Some(i) => args.get(i + 1).cloned().map(Some),
+        None => Some(None),
+    }
+}
+
+/// Takes `--shuffle <mode>` and `--seed <n>` out of `args`. `None` if a value
+/// is missing or invalid, or `--seed` comes without `--shuffle`.
+fn shuffle_arg(args: &mut Vec<String>) -> Option<Option<Shuffle>> {
+    let mut take = |flag: &str| match args.iter().position(|a| a == flag) {
+        Some(i) if i + 1 < args.len() => {
+            let value = args.remove(i + 1);
+            args.remove(i);
+            Some(Some(value))
+        }
+        Some(_) => None,
+        None => Some(None),
+    };
+    let strategy = take("--shuffle")?;
+    let seed = take("--seed")?;
+    match (strategy, seed) {
+        (None, None) => Some(None),
+        (None, Some(_)) => None,
+        (Some(strategy), seed) => Some(Some(Shuffle {
+            strategy: strategy.parse().ok()?,
+            seed: match seed {
+                Some(seed) => Some(seed.parse().ok()?),
+                None => None,
+            },
+        })),
+    }
+}
+
+/// `play album`, `play artist` or `play folder`.
+fn browse_arg(args: &[String]) -> Option<Browse> {
+    match args {
+        [mode] if mode == "album" => Some(Browse::Album),
+        [mode] if mode == "artist" => Some(Browse::Artist),
+        [mode] if mode == "folder" => Some(Browse::Folder),
+        _ => None,
+    }
+}
+
+/// Positional arguments of `play` and `append`, unless a query or browse
+/// mode is given.
+fn targets_arg(args: &[String], query: &Option<String>) -> Vec<String> {
+    if query.is_some() || browse_arg(args).is_some() {
+        Vec::new()
+    } else {
+        args.to_vec()
+    }
+}
+
+/// Applies `--shuffle`, printing the seed so the order can be reproduced.
+fn shuffled(entries: Vec<Entry>, shuffle: Option<Shuffle>) -> std::io::Result<Vec<Entry>> {
+    let Some(shuffle) = shuffle else {
+        return Ok(entries);
+    };
+    let (entries, seed) = shuffle.apply(entries)?;
+    println!("Shuffled with seed {}.", seed);
+    Ok(entries)
+}
+
+/// Replaces the queue with the entries.
+fn play(entries: Vec<Entry>, shuffle: Option<Shuffle>) -> std::io::Result<()> {
+    let entries = shuffled(entries, shuffle)?;
+    playlist::enqueue(&entries, true)?;
+    println!("Playing {} track(s).", entries.len());
+    Ok(())
+}
+
+/// Queues entries at the end, or after the current track if `next`.
+fn append(entries: Vec<Entry>, next: bool, shuffle: Option<Shuffle>) -> std::io::Result<()> {
+    let entries = shuffled(entries, shuffle)?;
+    if next {
+        playlist::enqueue_next(&entries)?;
+        println!("Playing {} track(s) next.", entries.len());
+    } else {
+        playlist::enqueue(&entries, false)?;
+        println!("Appended {} track(s).", entries.len());
+    }
+    Ok(())
+}
+
 fn print_usage() {
     println!(
         "Usage: orpheus <command> [args]\n\n\
         Commands:\n\
         \tlist\t\t\tList all playlists\n\
-        \tcreate <name>\t\tCreate a new playlist\n\
-        \tedit\t\t\tEdit a playlist\n\
-        \tdelete\t\t\tDelete playlists\n\
-        \tplay\t\t\tSelect and play a track or playlist\n\
-        \tappend\t\t\tAppend tracks to queue\n\
+        \tcreate <name> [paths...|-]\n\
+        \t\t\t\tCreate a new playlist (from paths or stdin if given)\n\
+        \tcreate <name> --query <query>\n\
+        \t\t\t\tCreate a playlist of the tracks matching a query now\n\
+        \tcreate <name> --smart <query>\n\
+        \t\t\t\tCreate a playlist evaluated on every play\n\
+        \tplaylist add <name> <paths...>\n\
+        \t\t\t\tAppends tracks or directories to a playlist\n\
+        \tplaylist remove <name> <pattern>\n\
+        \t\t\t\tRemoves tracks whose path or title matches a glob\n\
+        \tplaylist show <name>\tLists the tracks of a playlist\n\
+        \tplaylist rename <old> <new>\n\
+        \t\t\t\tRenames a playlist\n\
+        \tplaylist copy <from> <to>\n\
+        \t\t\t\tCopies a playlist\n\
+        \tedit [playlist]\t\tEdit a playlist (selected if not given)\n\
+        \tdelete [playlists...]\tDelete playlists (selected if not given)\n\
+        \tplay [--query <q> | <path|dir|playlist|url>...]\n\
+        \t\t\t\tPlays the given tracks or a query, or selects a track or playlist\n\
+        \tplay album | artist | folder\n\
+        \t\t\t\tSelects albums, artists or directories and plays them in order\n\
+        \tappend [--query <q> | <path|dir|playlist|url>...]\n\
+        \t\t\t\tAppends the given tracks or a query, or selects tracks to append\n\
+        \tappend album | artist | folder\n\
+        \t\t\t\tSelects albums, artists or directories and appends them\n\
+        \tappend --next [...] | playnext [...]\n\
+        \t\t\t\tLike append, but inserts after the current track\n\
+        \tplay | append ... --shuffle <mode> [--seed <n>]\n\
+        \t\t\t\tShuffles first: tracks, album, artist or weighted\n\
         \treload\t\t\tReload mpv with updated configuration\n\
         \tjump\t\t\tJumps to a track in current queue\n\
+        \tqueue [list]\t\tLists the queue, marking the current track\n\
+        \tqueue remove [N...]\tRemoves tracks by position, or selects them\n\
+        \tqueue move <from> <to>\tMoves a track to another position\n\
+        \tqueue clear [--keep-current]\n\
+        \t\t\t\tEmpties the queue, optionally keeping the current track\n\
+        \tqueue save <name>\tSaves the queue as a playlist\n\
+        \tqueue shuffle\t\tShuffles the queue once\n\
+        \tsession save | restore\tSaves or restores the queue, position and settings\n\
         \tshuffle <true|false>\tEnables/disables queue shuffle (static)\n\
+        \trepeat [off|track|queue]\n\
+        \t\t\t\tShows or sets whether the track or the queue repeats\n\
+        \tstatus [--json]\t\tShows what is currently playing\n\
+        \tpause | resume | toggle\tPauses, resumes or toggles playback\n\
+        \tnext | prev\t\tSkips to the next/previous track in queue\n\
+        \tstop\t\t\tStops playback and clears the queue\n\
+        \tseek <+/-secs|mm:ss|N%>\tSeeks relative, absolute or by percentage\n\
+        \tscan [--full] [--dry-run]\tRefreshes the library index and reports changes\n\
+        \trescan [--dry-run]\tRebuilds the library index (same as scan --full)\n\
+        \timport <file> [name]\tImports an M3U/M3U8/PLS/XSPF/JSON/CUE playlist\n\
+        \texport <playlist> [--format pls|xspf|m3u8|json] [-o file]\n\
+        \t\t\t\tExports a playlist (to stdout by default)\n\
+        \tvolume [N|+N|-N]\tShows or sets the volume (clamped to max_volume)\n\
+        \tmute [on|off|toggle]\tShows or changes the mute state\n\
         \thelp\t\t\tPrints this cheatsheet\n"
     );
 }
 
 fn print_completions(words: &[String]) -> std::io::Result<()> {
-    match words.len() {
-        0 | 1 => {
-            for cmd in Command::all() {
-                println!("{}", cmd);
-            }
-        }
-        _ => {}
+    for candidate in complete::complete(words) {
+        println!("{}", candidate);
     }
     Ok(())
 }
 
-fn main() -> std::io::Result<()> {
-    let config = config::Config::load()?;
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(error::exit_code(&e))
+        }
+    }
+}
+
+fn run() -> std::io::Result<()> {
+    let config = config::Config::load().map_err(|e| Error::Config(e.to_string()))?;
     config::CONFIG
         .set(config)
         .expect("Config already initialized");
 
-    let args: Vec<String> = env::args().skip(1).collect();
+    let args = env::args_os()
+        .skip(1)
+        .map(|arg| {
+            arg.into_string().map_err(|arg| {
+                Error::Usage(format!("{}: arguments must be valid UTF-8", arg.display()))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
 
     if args.is_empty() {
         print_usage();
@@ -101,43 +560,150 @@ fn main() -> std::io::Result<()> {
         return Ok(());
     }
 
-    if !mpv::is_running() {
+    let command = Command::parse(&args).ok_or_else(|| {
+        Error::Usage("unknown command or missing arguments, see `orpheus help`".into())
+    })?;
+
+    if command.needs_mpv() && !mpv::is_running() {
         mpv::spawn()?;
     }
 
-    let command = match Command::parse(&args) {
-        Some(cmd) => cmd,
-        None => {
-            eprintln!("Unknown command or missing arguments.");
-            print_usage();
-            return Ok(());
-        }
-    };
-
     match command {
         Command::List => {
             let playlists = list_playlists()?;
             println!("Playlists:");
             for p in playlists {
-                println!("{}", p.file_name().unwrap().to_string_lossy());
+                let name = p.file_name().unwrap().to_string_lossy();
+                if playlist::is_smart(&p) {
+                    println!("{}\t{}", name, playlist::read_smart(&p)?);
+                } else {
+                    println!("{}", name);
+                }
             }
         }
 
-        Command::Create { name } => create_playlist(&name)?,
+        Command::Create {
+            name,
+            smart: Some(query),
+            ..
+        } => playlist::create_smart_playlist(&name, &query)?,
+
+        Command::Create {
+            name,
+            query: Some(query),
+            ..
+        } => create_playlist(&name, &[], Some(&query))?,
+
+        Command::Create { name, paths, .. } => {
+            let paths = if paths == ["-"] {
+                std::io::stdin()
+                    .lines()
+                    .map_while(Result::ok)
+                    .map(|line| line.trim().to_string())
+                    .filter(|line| !line.is_empty())
+                    .collect::<Vec<_>>()
+            } else {
+                paths
+            };
+            if paths.is_empty() && args.get(2).is_some_and(|a| a == "-") {
+                return Err(std::io::Error::other("no paths on stdin"));
+            }
+            create_playlist(&name, &paths, None)?
+        }
+
+        Command::Playlist { action } => match action {
+            PlaylistAction::Add { name, paths } => playlist::add_to_playlist(&name, &paths)?,
+            PlaylistAction::Remove { name, pattern } => {
+                playlist::remove_from_playlist(&name, &pattern)?
+            }
+            PlaylistAction::Show { name } => playlist::show_playlist(&name)?,
+            PlaylistAction::Rename { old, new } => playlist::rename_playlist(&old, &new)?,
+            PlaylistAction::Copy { from, to } => playlist::copy_playlist(&from, &to)?,
+        },
 
-        Command::Edit => edit_playlist()?,
+        Command::Edit { name } => edit_playlist(name.as_deref())?,
 
-        Command::Delete => delete_playlists()?,
+        Command::Delete { names } => delete_playlists(&names)?,
+
+        Command::Play {
+            query: Some(query),
+            shuffle,
+            ..
+        } => {
+            let tracks = playlist::query_tracks(&query)?;
+            play(tracks.iter().map(Entry::from_track).collect(), shuffle)?;
+        }
+
+        Command::Append {
+            query: Some(query),
+            next,
+            shuffle,
+            ..
+        } => {
+            let tracks = playlist::query_tracks(&query)?;
+            append(
+                tracks.iter().map(Entry::from_track).collect(),
+                next,
+                shuffle,
+            )?;
+        }
+
+        Command::Play {
+            browse: Some(browse),
+            shuffle,
+            ..
+        } => {
+            let entries = match browse {
+                Browse::Album => playlist::select_group(Grouping::Album)?,
+                Browse::Artist => playlist::select_group(Grouping::Artist)?,
+                Browse::Folder => playlist::select_folder()?,
+            };
+            if entries.is_empty() {
+                return Err(Error::Cancelled.into());
+            }
+            play(entries, shuffle)?;
+        }
 
-        Command::Play => {
-            let options = vec!["playlist", "single file"];
+        Command::Append {
+            browse: Some(browse),
+            next,
+            shuffle,
+            ..
+        } => {
+            let entries = match browse {
+                Browse::Album => playlist::select_group(Grouping::Album)?,
+                Browse::Artist => playlist::select_group(Grouping::Artist)?,
+                Browse::Folder => playlist::select_folder()?,
+            };
+            if entries.is_empty() {
+                return Err(Error::Cancelled.into());
+            }
+            append(entries, next, shuffle)?;
+        }
+
+        Command::Play {
+            targets, shuffle, ..
+        } if !targets.is_empty() => {
+            play(playlist::entries_for(&targets)?, shuffle)?;
+        }
+
+        Command::Append {
+            targets,
+            next,
+            shuffle,
+            ..
+        } if !targets.is_empty() => {
+            append(playlist::entries_for(&targets)?, next, shuffle)?;
+        }
+
+        Command::Play { shuffle, .. } => {
+            let options = ["playlist", "single file"];
             let choice = run_fzf(
-                &options.iter().map(|s| PathBuf::from(s)).collect::<Vec<_>>(),
+                &options.iter().map(PathBuf::from).collect::<Vec<_>>(),
                 false,
             )?;
             if choice.is_empty() {
-                println!("No choice selected.");
-                return Ok(());
+                return Err(Error::Cancelled.into());
             }
 
             match choice[0].to_string_lossy().as_ref() {
@@ -145,24 +711,26 @@ fn main() -> std::io::Result<()> {
                     let playlists = list_playlists()?;
                     let selected = run_fzf(&playlists, false)?;
                     if selected.is_empty() {
-                        println!("No playlist selected.");
-                        return Ok(());
+                        return Err(Error::Cancelled.into());
+                    }
+                    match shuffle {
+                        Some(shuffle) => play(
+                            playlist::load_playlist(&selected[0])?.entries,
+                            Some(shuffle),
+                        )?,
+                        None => play_playlist(&selected[0])?,
                     }
-                    let playlist_path = &selected[0];
-                    send_command(MpvCommand::LoadPlaylist {
-                        path: playlist_path.to_string_lossy().into(),
-                    })?;
                 }
                 "single file" => {
-                    let files = scan_music()?;
-                    let selected = run_fzf(&files, false)?;
+                    let tracks = library::tracks()?;
+                    let selected = select_tracks(&tracks, false)?;
                     if selected.is_empty() {
-                        println!("No file selected.");
-                        return Ok(());
+                        return Err(Error::Cancelled.into());
                     }
-                    for file in &selected {
+                    for track in &selected {
                         send_command(MpvCommand::PlayFile {
-                            path: file.to_string_lossy().into(),
+                            path: mpv::path_arg(&track.path)?,
+                            segment: track.segment,
                         })?;
                     }
                 }
@@ -170,21 +738,25 @@ fn main() -> std::io::Result<()> {
             }
         }
 
-        Command::Append => {
-            let files = scan_music()?;
-            let selected = run_fzf(&files, true)?;
+        Command::Append { next, shuffle, .. } => {
+            let tracks = library::tracks()?;
+            let selected = select_tracks(&tracks, true)?;
             if selected.is_empty() {
-                println!("No file selected.");
-                return Ok(());
-            }
-            for file in &selected {
-                send_command(MpvCommand::AppendFile {
-                    path: file.to_string_lossy().into(),
-                })?;
+                return Err(Error::Cancelled.into());
             }
+            append(
+                selected.iter().map(Entry::from_track).collect(),
+                next,
+                shuffle,
+            )?;
         }
 
         Command::Reload => {
+            let session = Session::snapshot()?;
+            // An empty queue would replace a session saved before a reboot.
+            if session.len() > 0 {
+                session.save()?;
+            }
             send_command(MpvCommand::Quit)?;
 
             if mpv::is_running() {
@@ -193,16 +765,148 @@ fn main() -> std::io::Result<()> {
                     std::thread::sleep(std::time::Duration::from_millis(50));
                 }
             }
-            mpv::spawn()?
+            mpv::spawn()?;
+            session.restore()?
         }
 
-        Command::Jump => {
-            if let Some(idx) = jump()? {
-                send_command(MpvCommand::JumpTo { index: idx })?
+        Command::Jump => send_command(MpvCommand::JumpTo { index: jump()? })?,
+
+        Command::Queue { action } => match action {
+            QueueAction::List => playlist::list_queue()?,
+            QueueAction::Remove { positions } => playlist::remove_from_queue(&positions)?,
+            QueueAction::Move { from, to } => playlist::move_in_queue(from, to)?,
+            QueueAction::Clear { keep_current } => playlist::clear_queue(keep_current)?,
+            QueueAction::Save { name } => playlist::save_queue(&name)?,
+            QueueAction::Shuffle => send_command(MpvCommand::SetShuffle { enabled: true })?,
+        },
+
+        Command::Session { restore: false } => {
+            let session = Session::snapshot()?;
+            if session.len() == 0 {
+                return Err(std::io::Error::other(
+                    "queue is empty, keeping the saved session",
+                ));
             }
+            session.save()?;
+            println!("Saved session with {} track(s).", session.len());
         }
 
-        Command::Shuffle { enabled } => send_command(MpvCommand::SetShuffle { enabled })?,
+        Command::Session { restore: true } => {
+            let session = Session::load()?;
+            session.restore()?;
+            println!("Restored session with {} track(s).", session.len());
+        }
+
+        Command::Shuffle { enabled } => {
+            send_command(MpvCommand::SetShuffle { enabled })?;
+            // playlist-shuffle doesn't touch the option, so record it for `status`.
+            Client::connect()?.set_property("shuffle", enabled.into())?;
+        }
+
+        Command::Status { json } => {
+            let status = mpv::status()?;
+            if json {
+                println!("{}", status.to_json());
+            } else {
+                print!("{}", status);
+            }
+        }
+
+        Command::Pause => send_command(MpvCommand::SetPause { paused: true })?,
+
+        Command::Resume => send_command(MpvCommand::SetPause { paused: false })?,
+
+        Command::Toggle => send_command(MpvCommand::TogglePause)?,
+
+        Command::Next => send_command(MpvCommand::Next)?,
+
+        Command::Prev => send_command(MpvCommand::Prev)?,
+
+        Command::Stop => send_command(MpvCommand::Stop)?,
+
+        Command::Seek { target } => send_command(MpvCommand::Seek { target })?,
+
+        Command::Scan { full, dry_run } => {
+            let report = library::scan(full, dry_run)?;
+            if report.is_empty() {
+                println!("Library is up to date.");
+                return Ok(());
+            }
+            for path in &report.added {
+                println!("+ {}", path.display());
+            }
+            for path in &report.removed {
+                println!("- {}", path.display());
+            }
+            for path in &report.changed {
+                println!("~ {}", path.display());
+            }
+            println!(
+                "{}{} added, {} removed, {} changed",
+                if dry_run { "(dry run) " } else { "" },
+                report.added.len(),
+                report.removed.len(),
+                report.changed.len()
+            );
+        }
+
+        Command::Import { file, name } => {
+            let name = match name {
+                Some(name) => name,
+                None => file
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().into_owned())
+                    .ok_or_else(|| std::io::Error::other("invalid playlist file name"))?,
+            };
+            let path = playlist::import_playlist(&file, &name)?;
+            println!("Imported playlist at {}", path.display());
+        }
+
+        Command::Export {
+            name,
+            format,
+            output,
+        } => {
+            let format = format
+                .or_else(|| output.as_deref().and_then(Format::from_path))
+                .unwrap_or(Format::M3u8);
+            let rendered = playlist::export_playlist(&name, format)?;
+            match output {
+                Some(path) => std::fs::write(path, rendered)?,
+                None => print!("{}", rendered),
+            }
+        }
+
+        Command::Volume { change } => {
+            let volume = match change {
+                Some(change) => mpv::change_volume(change)?,
+                None => mpv::get_volume(&mut Client::connect()?)?,
+            };
+            println!("Volume: {:.0}%", volume);
+        }
+
+        Command::Mute { muted } => {
+            let mut client = Client::connect()?;
+            let cmd = match muted {
+                Some(MuteChange::On) => Some(MpvCommand::SetMute { muted: true }),
+                Some(MuteChange::Off) => Some(MpvCommand::SetMute { muted: false }),
+                Some(MuteChange::Toggle) => Some(MpvCommand::ToggleMute),
+                None => None,
+            };
+            if let Some(cmd) = cmd {
+                client.request(&cmd)?;
+            }
+            let muted = mpv::get_mute(&mut client)?;
+            println!("Mute: {}", if muted { "on" } else { "off" });
+        }
+
+        Command::Repeat { mode } => {
+            let mut client = Client::connect()?;
+            if let Some(mode) = mode {
+                mpv::set_repeat(&mut client, mode)?;
+            }
+            println!("Repeat: {}", mpv::get_repeat(&mut client)?);
+        }
 
         Command::Help => print_usage(),
     }
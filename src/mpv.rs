@@ -2,61 +2,506 @@ use std::{
     fmt,
     io::{self, BufRead, BufReader, Write},
     os::unix::net::UnixStream,
-    path::Path,
+    path::{Path, PathBuf},
     process::Command,
+    str::FromStr,
     thread::sleep,
     time::Duration,
 };
 
-use crate::config::CONFIG;
+use crate::config::{CONFIG, data_dir};
+use crate::error::Error;
+use crate::json::Json;
+
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub enum MpvCommand {
     /// Load a new playlist file (replace current playlist)
     LoadPlaylist { path: String },
     /// Append a track to the playlist
-    AppendFile { path: String },
+    AppendFile {
+        path: String,
+        segment: Option<Segment>,
+    },
     /// Play a single file (replace current playlist)
-    PlayFile { path: String },
+    PlayFile {
+        path: String,
+        segment: Option<Segment>,
+    },
     /// Quit mpv gracefully
     Quit,
     /// Get the current queue
     GetQueue,
     /// Jump to a track in the playlist by index
     JumpTo { index: usize },
+    /// Remove the playlist entry at the index
+    RemoveEntry { index: usize },
+    /// Move the entry at `from` so that it ends up before the entry at `to`
+    MoveEntry { from: usize, to: usize },
+    /// Remove every entry except the current one
+    ClearQueue,
     /// Enable/disable queue shuffling
     SetShuffle { enabled: bool },
+    /// Pause or resume playback
+    SetPause { paused: bool },
+    /// Flip the pause state
+    TogglePause,
+    /// Skip to the next track in the playlist
+    Next,
+    /// Go back to the previous track in the playlist
+    Prev,
+    /// Stop playback and clear the playlist
+    Stop,
+    /// Seek within the current track
+    Seek { target: SeekTarget },
+    /// Set the volume in percent
+    SetVolume { volume: f64 },
+    /// Mute or unmute audio
+    SetMute { muted: bool },
+    /// Flip the mute state
+    ToggleMute,
+    /// Repeat the current track forever, or not
+    SetLoopFile { enabled: bool },
+    /// Repeat the whole queue forever, or not
+    SetLoopPlaylist { enabled: bool },
+}
+
+/// Part of a file to play, e.g. one track of a CUE image. In seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Segment {
+    pub start: f64,
+    /// `None` plays to the end of the file.
+    pub end: Option<f64>,
 }
 
-impl fmt::Display for MpvCommand {
+impl Segment {
+    /// Per-file options for `loadfile`.
+    fn options(&self) -> String {
+        match self.end {
+            Some(end) => format!("start={},end={}", self.start, end),
+            None => format!("start={}", self.start),
+        }
+    }
+}
+
+/// `loadfile <path> <flags>`, plus the segment as per-file options. The
+/// options come after the insertion index since mpv 0.38.
+fn loadfile(path: &str, flags: &str, segment: &Option<Segment>) -> Vec<Json> {
+    let mut args = vec!["loadfile".into(), path.into(), flags.into()];
+    if let Some(segment) = segment {
+        args.push(Json::from(-1i64));
+        args.push(segment.options().into());
+    }
+    args
+}
+
+fn loop_value(enabled: bool) -> Json {
+    if enabled { "inf" } else { "no" }.into()
+}
+
+/// What plays again once the end is reached, on top of mpv's `loop-file`
+/// and `loop-playlist`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Repeat {
+    Off,
+    Track,
+    Queue,
+}
+
+impl Repeat {
+    /// Looping the file wins, since the queue never ends while it's on.
+    fn from_loops(loop_file: bool, loop_playlist: bool) -> Repeat {
+        match (loop_file, loop_playlist) {
+            (true, _) => Repeat::Track,
+            (false, true) => Repeat::Queue,
+            (false, false) => Repeat::Off,
+        }
+    }
+}
+
+impl FromStr for Repeat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Repeat::Off),
+            "track" => Ok(Repeat::Track),
+            "queue" => Ok(Repeat::Queue),
+            _ => Err(format!("invalid repeat mode `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for Repeat {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Repeat::Off => "off",
+            Repeat::Track => "track",
+            Repeat::Queue => "queue",
+        })
+    }
+}
+
+pub fn get_repeat(client: &mut Client) -> io::Result<Repeat> {
+    Ok(Repeat::from_loops(
+        is_on(client.get_optional_property("loop-file")?),
+        is_on(client.get_optional_property("loop-playlist")?),
+    ))
+}
+
+pub fn set_repeat(client: &mut Client, repeat: Repeat) -> io::Result<()> {
+    client.request(&MpvCommand::SetLoopFile {
+        enabled: repeat == Repeat::Track,
+    })?;
+    client.request(&MpvCommand::SetLoopPlaylist {
+        enabled: repeat == Repeat::Queue,
+    })?;
+    Ok(())
+}
+
+/// Flags and loop options come back as booleans, `"inf"`/`"no"` or counts.
+fn is_on(value: Option<Json>) -> bool {
+    match value {
+        Some(Json::Bool(b)) => b,
+        Some(Json::String(s)) => s != "no",
+        Some(Json::Number(n)) => n > 0.0,
+        _ => false,
+    }
+}
+
+/// Where to seek to, as given on the command line.
+#[derive(Debug, Clone, Copy)]
+pub enum SeekTarget {
+    /// `+10`, `-1:30`: offset in seconds from the current position
+    Relative(f64),
+    /// `90`, `1:30`: position in seconds from the start
+    Absolute(f64),
+    /// `50%`: position as a percentage of the duration
+    Percent(f64),
+}
+
+impl FromStr for SeekTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid seek target `{}`", s);
+
+        if let Some(percent) = s.strip_suffix('%') {
+            let value = parse_finite(percent).ok_or_else(invalid)?;
+            return Ok(SeekTarget::Percent(value.clamp(0.0, 100.0)));
+        }
+
+        let (sign, time) = match s.as_bytes().first() {
+            Some(b'+') => (Some(1.0), &s[1..]),
+            Some(b'-') => (Some(-1.0), &s[1..]),
+            _ => (None, s),
+        };
+        let secs = parse_time(time).ok_or_else(invalid)?;
+
+        Ok(match sign {
+            Some(sign) => SeekTarget::Relative(sign * secs),
+            None => SeekTarget::Absolute(secs),
+        })
+    }
+}
+
+/// A `volume` argument: absolute (`60`) or a relative step (`+5`, `-5`).
+#[derive(Debug, Clone, Copy)]
+pub enum VolumeChange {
+    Set(f64),
+    Step(f64),
+}
+
+impl FromStr for VolumeChange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = parse_finite(s).ok_or_else(|| format!("invalid volume `{}`", s))?;
+        if s.starts_with('+') || s.starts_with('-') {
+            Ok(VolumeChange::Step(value))
+        } else {
+            Ok(VolumeChange::Set(value))
+        }
+    }
+}
+
+/// Applies `change` clamped to `[0, max_volume]` and returns the volume mpv reports back.
+pub fn change_volume(change: VolumeChange) -> io::Result<f64> {
+    let config = CONFIG.get().expect("config not initialized");
+    let mut client = Client::connect()?;
+
+    let target = match change {
+        VolumeChange::Set(volume) => volume,
+        VolumeChange::Step(step) => get_volume(&mut client)? + step,
+    };
+    client.request(&MpvCommand::SetVolume {
+        volume: target.clamp(0.0, config.max_volume),
+    })?;
+
+    let volume = get_volume(&mut client)?;
+    save_volume(volume)?;
+    Ok(volume)
+}
+
+pub fn get_volume(client: &mut Client) -> io::Result<f64> {
+    client
+        .get_property("volume")?
+        .as_f64()
+        .ok_or_else(|| MpvError::Protocol("volume is not a number".into()).into())
+}
+
+pub fn get_mute(client: &mut Client) -> io::Result<bool> {
+    match client.get_property("mute")? {
+        Json::Bool(muted) => Ok(muted),
+        _ => Err(MpvError::Protocol("mute is not a boolean".into()).into()),
+    }
+}
+
+fn volume_file() -> PathBuf {
+    data_dir().join("volume")
+}
+
+fn save_volume(volume: f64) -> io::Result<()> {
+    std::fs::write(volume_file(), format!("{}\n", volume))
+}
+
+fn saved_volume() -> Option<f64> {
+    std::fs::read_to_string(volume_file())
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+}
+
+/// Like `f64::from_str`, but without `nan` and `inf`, which mpv rejects.
+fn parse_finite(s: &str) -> Option<f64> {
+    s.parse::<f64>().ok().filter(|v| v.is_finite())
+}
+
+/// Parses `ss`, `mm:ss` or `hh:mm:ss` into seconds.
+fn parse_time(s: &str) -> Option<f64> {
+    let mut secs = 0.0;
+    let parts: Vec<&str> = s.split(':').collect();
+    if parts.len() > 3 {
+        return None;
+    }
+    for part in parts {
+        let value = parse_finite(part)?;
+        if value < 0.0 {
+            return None;
+        }
+        secs = secs * 60.0 + value;
+    }
+    Some(secs)
+}
+
+impl MpvCommand {
+    /// Arguments of the mpv `command` array for this command.
+    fn args(&self) -> Vec<Json> {
         match self {
             MpvCommand::LoadPlaylist { path } => {
-                write!(f, r#""loadlist", "{}", "replace""#, path)
+                vec!["loadlist".into(), path.as_str().into(), "replace".into()]
             }
-            MpvCommand::AppendFile { path } => {
-                write!(f, r#""loadfile", "{}", "append-play""#, path)
+            MpvCommand::AppendFile { path, segment } => loadfile(path, "append-play", segment),
+            MpvCommand::PlayFile { path, segment } => loadfile(path, "replace", segment),
+            MpvCommand::Quit => vec!["quit".into()],
+            MpvCommand::GetQueue => vec!["get_property".into(), "playlist".into()],
+            MpvCommand::JumpTo { index } => {
+                vec![
+                    "set_property".into(),
+                    "playlist-pos".into(),
+                    (*index).into(),
+                ]
             }
-            MpvCommand::PlayFile { path } => {
-                write!(f, r#""loadfile", "{}", "replace""#, path)
+            MpvCommand::RemoveEntry { index } => {
+                vec!["playlist-remove".into(), (*index).into()]
             }
-            MpvCommand::Quit => write!(f, r#""quit""#),
-            MpvCommand::GetQueue => write!(f, r#""get_property", "playlist""#),
-            MpvCommand::JumpTo { index } => {
-                write!(f, r#""set_property", "playlist-pos", {}"#, index)
+            MpvCommand::MoveEntry { from, to } => {
+                vec!["playlist-move".into(), (*from).into(), (*to).into()]
             }
+            MpvCommand::ClearQueue => vec!["playlist-clear".into()],
             MpvCommand::SetShuffle { enabled } => {
                 if *enabled {
-                    write!(f, r#""playlist-shuffle""#)
+                    vec!["playlist-shuffle".into()]
                 } else {
-                    write!(f, r#""playlist-unshuffle""#)
+                    vec!["playlist-unshuffle".into()]
                 }
             }
+            MpvCommand::SetPause { paused } => {
+                vec!["set_property".into(), "pause".into(), (*paused).into()]
+            }
+            MpvCommand::TogglePause => vec!["cycle".into(), "pause".into()],
+            MpvCommand::Next => vec!["playlist-next".into()],
+            MpvCommand::Prev => vec!["playlist-prev".into()],
+            MpvCommand::Stop => vec!["stop".into()],
+            MpvCommand::Seek { target } => {
+                let (secs, flags) = match *target {
+                    SeekTarget::Relative(secs) => (secs, "relative"),
+                    SeekTarget::Absolute(secs) => (secs, "absolute"),
+                    SeekTarget::Percent(percent) => (percent, "absolute-percent"),
+                };
+                vec!["seek".into(), secs.into(), flags.into()]
+            }
+            MpvCommand::SetVolume { volume } => {
+                vec!["set_property".into(), "volume".into(), (*volume).into()]
+            }
+            MpvCommand::SetMute { muted } => {
+                vec!["set_property".into(), "mute".into(), (*muted).into()]
+            }
+            MpvCommand::ToggleMute => vec!["cycle".into(), "mute".into()],
+            MpvCommand::SetLoopFile { enabled } => {
+                vec![
+                    "set_property".into(),
+                    "loop-file".into(),
+                    loop_value(*enabled),
+                ]
+            }
+            MpvCommand::SetLoopPlaylist { enabled } => {
+                vec![
+                    "set_property".into(),
+                    "loop-playlist".into(),
+                    loop_value(*enabled),
+                ]
+            }
         }
     }
 }
 
-fn mpv_json_command(cmd: MpvCommand) -> String {
-    format!(r#"{{ "command": [{}] }}"#, cmd) + "\n"
+/// Errors returned while talking to mpv over its IPC socket.
+#[derive(Debug)]
+pub enum MpvError {
+    /// The socket could not be reached or the connection broke.
+    Io(io::Error),
+    /// mpv answered the request with a non-success `error` field.
+    Command(String),
+    /// mpv sent something that is not a valid reply.
+    Protocol(String),
+}
+
+impl fmt::Display for MpvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MpvError::Io(e) => write!(f, "mpv IPC error: {}", e),
+            MpvError::Command(e) => write!(f, "mpv error: {}", e),
+            MpvError::Protocol(e) => write!(f, "invalid mpv reply: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MpvError {}
+
+impl From<io::Error> for MpvError {
+    fn from(e: io::Error) -> Self {
+        MpvError::Io(e)
+    }
+}
+
+impl From<MpvError> for io::Error {
+    fn from(e: MpvError) -> Self {
+        match e {
+            MpvError::Io(e) => Error::MpvUnreachable(e.to_string()).into(),
+            e => io::Error::other(e),
+        }
+    }
+}
+
+/// A connection to mpv's JSON IPC that matches replies to requests by `request_id`.
+pub struct Client {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+    next_id: u64,
+}
+
+impl Client {
+    pub fn connect() -> Result<Self, MpvError> {
+        let config = CONFIG.get().expect("config not initialized");
+        let stream = UnixStream::connect(&config.socket_path).map_err(|e| {
+            io::Error::new(e.kind(), format!("{}: {}", config.socket_path.display(), e))
+        })?;
+        stream.set_read_timeout(Some(REPLY_TIMEOUT))?;
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            next_id: 1,
+        })
+    }
+
+    /// Sends `cmd` and returns the `data` field of its reply.
+    pub fn request(&mut self, cmd: &MpvCommand) -> Result<Json, MpvError> {
+        let reply = self.raw(cmd.args());
+        match (cmd, reply) {
+            // mpv may close the socket before it gets to answer a quit.
+            (MpvCommand::Quit, Err(MpvError::Io(_) | MpvError::Protocol(_))) => Ok(Json::Null),
+            (_, reply) => reply,
+        }
+    }
+
+    pub fn get_property(&mut self, name: &str) -> Result<Json, MpvError> {
+        self.raw(vec!["get_property".into(), name.into()])
+    }
+
+    /// Like `get_property`, but maps "property unavailable" (e.g. `time-pos`
+    /// while idle) to `None`.
+    pub fn get_optional_property(&mut self, name: &str) -> Result<Option<Json>, MpvError> {
+        match self.get_property(name) {
+            Ok(Json::Null) => Ok(None),
+            Ok(value) => Ok(Some(value)),
+            Err(MpvError::Command(e)) if e == "property unavailable" => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn set_property(&mut self, name: &str, value: Json) -> Result<(), MpvError> {
+        self.raw(vec!["set_property".into(), name.into(), value])?;
+        Ok(())
+    }
+
+    fn raw(&mut self, args: Vec<Json>) -> Result<Json, MpvError> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let request = Json::object([
+            ("command", Json::Array(args)),
+            ("request_id", Json::from(id as i64)),
+        ]);
+        self.writer.write_all(format!("{}\n", request).as_bytes())?;
+        self.writer.flush()?;
+
+        loop {
+            let mut line = Vec::new();
+            if self.reader.read_until(b'\n', &mut line)? == 0 {
+                return Err(MpvError::Protocol("connection closed by mpv".into()));
+            }
+            let line = line.trim_ascii();
+            if line.is_empty() {
+                continue;
+            }
+
+            let reply = Json::parse(line).map_err(MpvError::Protocol)?;
+            // Events are interleaved with replies on the same socket.
+            if reply.get("event").is_some() {
+                continue;
+            }
+            if reply.get("request_id").and_then(Json::as_i64) != Some(id as i64) {
+                continue;
+            }
+
+            return match reply.get("error").and_then(Json::as_str) {
+                Some("success") => Ok(reply.get("data").cloned().unwrap_or(Json::Null)),
+                Some(e) => Err(MpvError::Command(e.to_string())),
+                None => Err(MpvError::Protocol("reply without `error` field".into())),
+            };
+        }
+    }
+}
+
+/// A path as mpv's JSON IPC takes it. Paths that aren't valid UTF-8 can't
+/// be sent intact, so they are refused rather than mangled.
+pub fn path_arg(path: &Path) -> io::Result<String> {
+    path.to_str().map(String::from).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{}: mpv can only be sent UTF-8 paths", path.display()),
+        )
+    })
 }
 
 pub fn is_running() -> bool {
@@ -83,13 +528,21 @@ pub fn spawn() -> io::Result<()> {
 
     let mut cmd = Command::new("mpv");
 
-    if let Some(ref path) = config.mpris_plugin_path {
-        if path.exists() {
-            cmd.arg(format!(
-                "--script={}",
-                path.to_str().expect("invalid mpris_plugin_path")
-            ));
-        }
+    if let Some(ref path) = config.mpris_plugin_path
+        && path.exists()
+    {
+        cmd.arg(format!(
+            "--script={}",
+            path.to_str().expect("invalid mpris_plugin_path")
+        ));
+    }
+
+    // mpv refuses volumes above volume-max, which can't go below 100.
+    cmd.arg(format!("--volume-max={}", config.max_volume.max(100.0)));
+    if config.restore_volume
+        && let Some(volume) = saved_volume()
+    {
+        cmd.arg(format!("--volume={}", volume.clamp(0.0, config.max_volume)));
     }
 
     cmd.arg("--idle=yes")
@@ -102,7 +555,10 @@ pub fn spawn() -> io::Result<()> {
             config.socket_path.to_str().expect("invalid socket_path")
         ));
 
-    cmd.spawn()?;
+    cmd.spawn().map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => Error::MpvUnreachable("mpv is not installed".into()),
+        _ => Error::MpvUnreachable(format!("failed to start mpv: {}", e)),
+    })?;
 
     for _ in 0..10 {
         if Path::new(&config.socket_path).exists() {
@@ -111,62 +567,223 @@ pub fn spawn() -> io::Result<()> {
         sleep(Duration::from_millis(200));
     }
     if !Path::new(&config.socket_path).exists() {
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            "Failed to create mpv socket",
-        ));
+        return Err(Error::MpvUnreachable(format!(
+            "mpv didn't create {}",
+            config.socket_path.display()
+        ))
+        .into());
     }
 
     Ok(())
 }
 
 pub fn send_command(cmd: MpvCommand) -> io::Result<()> {
-    let config = CONFIG.get().expect("config not initialized");
-    let mut stream = UnixStream::connect(&config.socket_path)?;
+    Client::connect()?.request(&cmd)?;
+    Ok(())
+}
 
-    stream.write_all(mpv_json_command(cmd).as_bytes())?;
-    stream.flush()?;
+/// One entry of mpv's playlist.
+pub struct QueueEntry {
+    pub filename: String,
+    /// Title from the loaded playlist or the file's metadata, if mpv has one.
+    pub title: Option<String>,
+    pub current: bool,
+}
 
-    Ok(())
+pub fn get_queue() -> io::Result<Vec<QueueEntry>> {
+    let queue = Client::connect()?.request(&MpvCommand::GetQueue)?;
+    let entries = queue
+        .as_array()
+        .ok_or_else(|| MpvError::Protocol("playlist is not an array".into()))?;
+
+    Ok(entries
+        .iter()
+        .filter_map(|e| {
+            Some(QueueEntry {
+                filename: e.get("filename")?.as_str()?.to_string(),
+                title: e.get("title").and_then(Json::as_str).map(String::from),
+                current: matches!(e.get("current"), Some(Json::Bool(true))),
+            })
+        })
+        .collect())
 }
 
-pub fn get_queue() -> io::Result<Vec<String>> {
-    let config = CONFIG.get().expect("config not initialized");
-    let mut stream = UnixStream::connect(&config.socket_path)?;
+/// Snapshot of what mpv is currently doing.
+pub struct Status {
+    pub path: Option<String>,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub time_pos: Option<f64>,
+    pub duration: Option<f64>,
+    pub paused: bool,
+    pub volume: f64,
+    pub shuffle: bool,
+    pub loop_file: bool,
+    pub loop_playlist: bool,
+    /// Zero-based position in the queue, `None` when nothing is loaded.
+    pub position: Option<usize>,
+    pub queue_len: usize,
+}
 
-    let cmd = mpv_json_command(MpvCommand::GetQueue);
-    stream.write_all(cmd.as_bytes())?;
-    stream.write_all(b"\n")?;
-    stream.flush()?;
+pub fn status() -> io::Result<Status> {
+    let mut client = Client::connect()?;
 
-    let mut reader = BufReader::new(stream);
-    let mut response = String::new();
+    let metadata = client.get_optional_property("metadata")?;
+    // Tag keys come straight from the file, so their case varies by format.
+    let tag = |key: &str| match &metadata {
+        Some(Json::Object(members)) => members
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .and_then(|(_, v)| v.as_str())
+            .map(String::from),
+        _ => None,
+    };
+    let title = tag("title").or(client
+        .get_optional_property("media-title")?
+        .and_then(|v| v.as_str().map(String::from)));
 
-    loop {
-        let mut line = String::new();
-        let bytes = reader.read_line(&mut line)?;
-        if bytes == 0 {
-            break;
+    Ok(Status {
+        path: client
+            .get_optional_property("path")?
+            .and_then(|v| v.as_str().map(String::from)),
+        title,
+        artist: tag("artist"),
+        album: tag("album"),
+        time_pos: client
+            .get_optional_property("time-pos")?
+            .and_then(|v| v.as_f64()),
+        duration: client
+            .get_optional_property("duration")?
+            .and_then(|v| v.as_f64()),
+        paused: is_on(client.get_optional_property("pause")?),
+        volume: client
+            .get_optional_property("volume")?
+            .and_then(|v| v.as_f64())
+            .unwrap_or(0.0),
+        shuffle: is_on(client.get_optional_property("shuffle")?),
+        loop_file: is_on(client.get_optional_property("loop-file")?),
+        loop_playlist: is_on(client.get_optional_property("loop-playlist")?),
+        position: client
+            .get_optional_property("playlist-pos")?
+            .and_then(|v| v.as_i64())
+            .and_then(|p| usize::try_from(p).ok()),
+        queue_len: client
+            .get_optional_property("playlist-count")?
+            .and_then(|v| v.as_i64())
+            .unwrap_or(0) as usize,
+    })
+}
+
+impl Status {
+    pub fn repeat(&self) -> Repeat {
+        Repeat::from_loops(self.loop_file, self.loop_playlist)
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object([
+            ("path", self.path.clone().into()),
+            ("title", self.title.clone().into()),
+            ("artist", self.artist.clone().into()),
+            ("album", self.album.clone().into()),
+            ("time_pos", self.time_pos.into()),
+            ("duration", self.duration.into()),
+            ("paused", self.paused.into()),
+            ("volume", self.volume.into()),
+            ("shuffle", self.shuffle.into()),
+            ("loop_file", self.loop_file.into()),
+            ("loop_playlist", self.loop_playlist.into()),
+            ("repeat", self.repeat().to_string().into()),
+            ("position", self.position.into()),
+            ("queue_len", self.queue_len.into()),
+        ])
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(path) = &self.path else {
+            return writeln!(f, "Idle");
+        };
+
+        let state = if self.paused { "Paused" } else { "Playing" };
+        match (&self.artist, &self.title) {
+            (Some(artist), Some(title)) => writeln!(f, "{}: {} - {}", state, artist, title)?,
+            (None, Some(title)) => writeln!(f, "{}: {}", state, title)?,
+            _ => writeln!(f, "{}: {}", state, path)?,
         }
-        response.push_str(&line);
-        if line.contains(r#""error":"success""#) {
-            break;
+        if let Some(album) = &self.album {
+            writeln!(f, "Album:\t{}", album)?;
+        }
+        writeln!(f, "File:\t{}", path)?;
+        writeln!(
+            f,
+            "Time:\t{} / {}",
+            format_time(self.time_pos),
+            format_time(self.duration)
+        )?;
+        if let Some(pos) = self.position {
+            writeln!(f, "Queue:\t{}/{}", pos + 1, self.queue_len)?;
         }
+        let on_off = |b: bool| if b { "on" } else { "off" };
+        writeln!(
+            f,
+            "Volume:\t{:.0}%\tShuffle: {}\tRepeat: {}",
+            self.volume,
+            on_off(self.shuffle),
+            self.repeat()
+        )
     }
+}
 
-    let mut queue = Vec::new();
-    let mut start = 0;
-    while let Some(pos) = response[start..].find(r#""filename":"#) {
-        let pos = start + pos;
-        let rest = &response[pos + 12..];
-        if let Some(end) = rest.find('"') {
-            let filename = rest[..end].to_string();
-            queue.push(filename);
-            start = pos + 12 + end;
-        } else {
-            break;
+pub fn format_time(secs: Option<f64>) -> String {
+    match secs {
+        Some(secs) if secs >= 0.0 => {
+            let secs = secs as u64;
+            if secs >= 3600 {
+                format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+            } else {
+                format!("{}:{:02}", secs / 60, secs % 60)
+            }
+        }
+        _ => "--:--".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seek_targets() {
+        assert!(matches!("+10".parse(), Ok(SeekTarget::Relative(10.0))));
+        assert!(matches!("-1:30".parse(), Ok(SeekTarget::Relative(-90.0))));
+        assert!(matches!(
+            "1:02:03".parse(),
+            Ok(SeekTarget::Absolute(3723.0))
+        ));
+        assert!(matches!("150%".parse(), Ok(SeekTarget::Percent(100.0))));
+        for invalid in [
+            "nan",
+            "inf",
+            "+infinity",
+            "NaN%",
+            "1:nan",
+            "1:-2",
+            "1:2:3:4",
+            "",
+        ] {
+            assert!(invalid.parse::<SeekTarget>().is_err(), "{}", invalid);
         }
     }
 
-    Ok(queue)
+    #[test]
+    fn volume_changes() {
+        assert!(matches!("60".parse(), Ok(VolumeChange::Set(60.0))));
+        assert!(matches!("+5".parse(), Ok(VolumeChange::Step(5.0))));
+        assert!(matches!("-5".parse(), Ok(VolumeChange::Step(-5.0))));
+        for invalid in ["nan", "inf", "-inf", "loud"] {
+            assert!(invalid.parse::<VolumeChange>().is_err(), "{}", invalid);
+        }
+    }
 }
@@ -1,191 +1,1180 @@
-use std::fs::{self, File};
-use std::io::{self, BufRead, BufReader, Write};
-use std::path::PathBuf;
-
-use crate::config::CONFIG;
-use crate::mpv::get_queue;
-use crate::ui::run_fzf;
-
-fn get_orpheus_dir() -> PathBuf {
-    let data_dir = std::env::var("XDG_DATA_HOME")
-        .map(PathBuf::from)
-        .unwrap_or_else(|_| {
-            let home = std::env::var("HOME").expect("HOME env var not set");
-            PathBuf::from(home).join(".local/share")
-        });
-    let orpheus_dir = data_dir.join("orpheus");
-    fs::create_dir_all(&orpheus_dir).unwrap();
-    orpheus_dir
-}
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config::{CONFIG, data_dir};
+use crate::cue;
+use crate::error::Error;
+use crate::formats::{self, Format};
+use crate::glob;
+use crate::library::{self, Grouping, Track};
+use crate::mpv::{
+    Client, MpvCommand, QueueEntry, Segment, format_time, get_queue, path_arg, send_command,
+};
+use crate::query::Query;
+use crate::ui::{run_fzf, run_fzf_labeled, select_tracks};
+use crate::walk::{self, Walk, WalkOptions};
 
 pub fn list_playlists() -> io::Result<Vec<PathBuf>> {
-    let orpheus_dir = get_orpheus_dir();
+    let orpheus_dir = data_dir();
     let mut playlists = Vec::new();
     for entry in fs::read_dir(&orpheus_dir)? {
         let entry = entry?;
         let path = entry.path();
-        if path.is_file() && path.extension().map_or(false, |e| e == "m3u") {
+        if path.is_file()
+            && path
+                .extension()
+                .is_some_and(|e| e == "m3u" || e == "m3u8" || e == "smart")
+        {
             playlists.push(path);
         }
     }
     Ok(playlists)
 }
 
-pub fn create_playlist(name: &str) -> io::Result<()> {
-    let files = scan_music()?;
-    let selected = run_fzf(&files, true)?;
-    let path = write_playlist(name, &selected)?;
+/// Smart playlists are `<name>.smart` files holding a query, see `query.rs`.
+pub fn is_smart(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "smart")
+}
+
+/// The query of a smart playlist. Lines are joined, `#` lines are comments.
+pub fn read_smart(path: &Path) -> io::Result<String> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .collect::<Vec<_>>()
+        .join(" "))
+}
+
+/// Reads a static playlist in any supported format, or evaluates a smart one
+/// against the library.
+pub fn load_playlist(path: &Path) -> io::Result<Playlist> {
+    if !is_smart(path) {
+        return formats::read(path);
+    }
+    let query: Query = read_smart(path)?.parse().map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), e),
+        )
+    })?;
+    Ok(Playlist {
+        entries: query
+            .run(&library::tracks()?)
+            .iter()
+            .map(Entry::from_track)
+            .collect(),
+        ..Playlist::default()
+    })
+}
+
+/// Finds a static playlist for editing; smart ones are edited as text.
+fn find_static_playlist(name: &str) -> io::Result<PathBuf> {
+    let path = find_playlist(name)?;
+    if is_smart(&path) {
+        return Err(io::Error::other(format!(
+            "`{}` is a smart playlist, edit its query in {}",
+            name,
+            path.display()
+        )));
+    }
+    Ok(path)
+}
+
+/// One track of an M3U playlist together with the directives describing it.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// The location exactly as written in the playlist (possibly relative).
+    pub location: String,
+    /// `location` resolved against the playlist's directory.
+    pub path: PathBuf,
+    /// `#EXTINF` duration in seconds; `-1` (unknown) is stored as `None`.
+    pub duration: Option<f64>,
+    /// `#EXTINF` display title, usually "Artist - Title".
+    pub title: Option<String>,
+    /// `#EXTINF` attributes between the duration and the comma, e.g. `tvg-id="x"`.
+    pub attributes: Option<String>,
+    /// `#EXTALB`
+    pub album: Option<String>,
+    /// `#EXTART`
+    pub artist: Option<String>,
+    /// `#EXTVLCOPT:start-time=`/`stop-time=`, e.g. for tracks of a CUE image.
+    pub segment: Option<Segment>,
+    /// Unknown `#` lines preceding the entry, kept verbatim.
+    pub directives: Vec<String>,
+}
+
+impl Entry {
+    pub fn new(path: PathBuf) -> Entry {
+        Entry {
+            location: path.to_string_lossy().into_owned(),
+            path,
+            duration: None,
+            title: None,
+            attributes: None,
+            album: None,
+            artist: None,
+            segment: None,
+            directives: Vec::new(),
+        }
+    }
+
+    /// Builds an entry carrying the track's tags as `#EXTINF`/`#EXTALB`/`#EXTART`.
+    pub fn from_track(track: &Track) -> Entry {
+        let title = match (&track.tags.artist, &track.tags.title) {
+            (Some(artist), Some(title)) => Some(format!("{} - {}", artist, title)),
+            (None, Some(title)) => Some(title.clone()),
+            _ => None,
+        };
+        Entry {
+            duration: track.duration,
+            title,
+            album: track.tags.album.clone(),
+            artist: track.tags.artist.clone(),
+            segment: track.segment,
+            ..Entry::new(track.path.clone())
+        }
+    }
+
+    /// Whether the entry refers to `track`, telling tracks of one CUE image apart.
+    pub fn is_track(&self, track: &Track) -> bool {
+        self.path == track.path && self.segment.map(|s| s.start) == track.segment.map(|s| s.start)
+    }
+
+    /// What to show for the entry in fzf.
+    pub fn label(&self) -> String {
+        match &self.title {
+            Some(title) => title.clone(),
+            None => self.location.clone(),
+        }
+    }
+
+    /// The command that queues the entry in mpv.
+    pub fn enqueue_command(&self, replace: bool) -> io::Result<MpvCommand> {
+        let path = path_arg(&self.path)?;
+        let segment = self.segment;
+        Ok(if replace {
+            MpvCommand::PlayFile { path, segment }
+        } else {
+            MpvCommand::AppendFile { path, segment }
+        })
+    }
+
+    fn has_extinf(&self) -> bool {
+        self.duration.is_some() || self.title.is_some() || self.attributes.is_some()
+    }
+}
+
+/// An extended M3U/M3U8 playlist.
+#[derive(Debug, Clone, Default)]
+pub struct Playlist {
+    /// `#PLAYLIST` display name
+    pub name: Option<String>,
+    /// Playlist-wide directives (e.g. `#EXTENC`), kept verbatim.
+    pub header: Vec<String>,
+    pub entries: Vec<Entry>,
+    /// Directives after the last entry, kept verbatim.
+    pub trailer: Vec<String>,
+}
+
+impl Playlist {
+    pub fn read(path: &Path) -> io::Result<Playlist> {
+        let bytes = fs::read(path)?;
+        // Plain .m3u files are traditionally Latin-1.
+        let content = match String::from_utf8(bytes) {
+            Ok(content) => content,
+            Err(e) => e.into_bytes().iter().map(|&b| b as char).collect(),
+        };
+        let base = path.parent().unwrap_or(Path::new("."));
+        Ok(Playlist::parse(&content, base))
+    }
+
+    /// Parses M3U text, resolving relative entries against `base`.
+    pub fn parse(content: &str, base: &Path) -> Playlist {
+        let mut playlist = Playlist::default();
+        let mut pending = Entry::new(PathBuf::new());
+
+        for line in content.lines() {
+            let line = line.trim_start_matches('\u{feff}').trim_end();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(directive) = line.strip_prefix('#') {
+                let (tag, value) = directive.split_once(':').unwrap_or((directive, ""));
+                match tag {
+                    "EXTM3U" => {}
+                    "PLAYLIST" => playlist.name = Some(value.to_string()),
+                    "EXTENC" => playlist.header.push(line.to_string()),
+                    "EXTINF" => parse_extinf(value, &mut pending),
+                    "EXTALB" => pending.album = Some(value.to_string()),
+                    "EXTART" => pending.artist = Some(value.to_string()),
+                    "EXTVLCOPT" if parse_vlcopt(value, &mut pending) => {}
+                    _ => pending.directives.push(line.to_string()),
+                }
+                continue;
+            }
+
+            let path = resolve_location(line, base);
+            let entry = Entry {
+                location: line.to_string(),
+                path,
+                ..std::mem::replace(&mut pending, Entry::new(PathBuf::new()))
+            };
+            playlist.entries.push(entry);
+        }
+
+        playlist.trailer = pending.directives;
+        playlist
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+impl fmt::Display for Playlist {
+    /// Renders the playlist as extended M3U.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "#EXTM3U")?;
+        if let Some(name) = &self.name {
+            writeln!(f, "#PLAYLIST:{}", name)?;
+        }
+        for line in &self.header {
+            writeln!(f, "{}", line)?;
+        }
+
+        for entry in &self.entries {
+            for line in &entry.directives {
+                writeln!(f, "{}", line)?;
+            }
+            if entry.has_extinf() {
+                write!(f, "#EXTINF:{}", format_duration(entry.duration))?;
+                if let Some(attributes) = &entry.attributes {
+                    write!(f, " {}", attributes)?;
+                }
+                writeln!(f, ",{}", entry.title.as_deref().unwrap_or_default())?;
+            }
+            if let Some(album) = &entry.album {
+                writeln!(f, "#EXTALB:{}", album)?;
+            }
+            if let Some(artist) = &entry.artist {
+                writeln!(f, "#EXTART:{}", artist)?;
+            }
+            if let Some(segment) = &entry.segment {
+                writeln!(f, "#EXTVLCOPT:start-time={}", segment.start)?;
+                if let Some(end) = segment.end {
+                    writeln!(f, "#EXTVLCOPT:stop-time={}", end)?;
+                }
+            }
+            writeln!(f, "{}", entry.location)?;
+        }
+
+        for line in &self.trailer {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// `#EXTINF:<duration> [attributes],<title>`
+fn parse_extinf(value: &str, entry: &mut Entry) {
+    let (info, title) = value.split_once(',').unwrap_or((value, ""));
+    let (duration, attributes) = info.trim().split_once(' ').unwrap_or((info.trim(), ""));
+
+    entry.duration = duration.parse::<f64>().ok().filter(|d| *d >= 0.0);
+    entry.attributes = Some(attributes.trim())
+        .filter(|a| !a.is_empty())
+        .map(String::from);
+    entry.title = Some(title.trim())
+        .filter(|t| !t.is_empty())
+        .map(String::from);
+}
+
+/// `#EXTVLCOPT:start-time=<seconds>` and `stop-time=`. Returns false for other
+/// options, which are kept as unknown directives.
+fn parse_vlcopt(value: &str, entry: &mut Entry) -> bool {
+    let Some((option, seconds)) = value.split_once('=') else {
+        return false;
+    };
+    let option = option.trim();
+    if option != "start-time" && option != "stop-time" {
+        return false;
+    }
+    let Ok(seconds) = seconds.trim().parse::<f64>() else {
+        return false;
+    };
+    let segment = entry.segment.get_or_insert(Segment {
+        start: 0.0,
+        end: None,
+    });
+    if option == "start-time" {
+        segment.start = seconds;
+    } else {
+        segment.end = Some(seconds);
+    }
+    true
+}
+
+fn format_duration(duration: Option<f64>) -> String {
+    match duration {
+        Some(d) if d.fract() == 0.0 => format!("{}", d as i64),
+        Some(d) => format!("{:.3}", d),
+        None => "-1".to_string(),
+    }
+}
+
+/// Resolves a playlist location against the playlist's directory. `file://`
+/// URLs become paths; other URLs and absolute paths are returned unchanged.
+pub fn resolve_location(location: &str, base: &Path) -> PathBuf {
+    if let Some(path) = location.strip_prefix("file://") {
+        return PathBuf::from(percent_decode(path));
+    }
+    if location.contains("://") {
+        return PathBuf::from(location);
+    }
+    let path = PathBuf::from(location);
+    if path.is_absolute() {
+        path
+    } else {
+        base.join(path)
+    }
+}
+
+pub fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Finds a playlist in the data dir by name, with or without extension.
+pub fn find_playlist(name: &str) -> io::Result<PathBuf> {
+    list_playlists()?
+        .into_iter()
+        .find(|p| {
+            p.file_stem().is_some_and(|s| s == name) || p.file_name().is_some_and(|s| s == name)
+        })
+        .ok_or_else(|| Error::NotFound(format!("playlist `{}` not found", name)).into())
+}
+
+/// Converts a playlist file of any supported format into an orpheus M3U,
+/// pointing entries that don't exist here at matching library tracks.
+pub fn import_playlist(file: &Path, name: &str) -> io::Result<PathBuf> {
+    ensure_new(name)?;
+    let mut playlist = formats::read(file)?;
+    // Relative locations would break once the playlist lives in the data dir.
+    for entry in &mut playlist.entries {
+        if !entry.location.contains("://") {
+            entry.location = entry.path.to_string_lossy().into_owned();
+        }
+    }
+    let rewrite = formats::rewrite_paths(&mut playlist, &library::tracks()?);
+    if rewrite.rewritten > 0 {
+        println!(
+            "Matched {} track(s) against the library.",
+            rewrite.rewritten
+        );
+    }
+    for path in &rewrite.missing {
+        eprintln!("warning: not found: {}", path.display());
+    }
+
+    write_playlist(name, &playlist)
+}
+
+pub fn export_playlist(name: &str, format: Format) -> io::Result<String> {
+    let playlist = load_playlist(&find_playlist(name)?)?;
+    Ok(formats::render(&playlist, format))
+}
+
+/// Creates a playlist from the tracks matching `query`, from `paths`, or from
+/// tracks picked in fzf when neither is given.
+pub fn create_playlist(name: &str, paths: &[String], query: Option<&str>) -> io::Result<()> {
+    ensure_new(name)?;
+    let entries = if let Some(query) = query {
+        query_tracks(query)?.iter().map(Entry::from_track).collect()
+    } else if paths.is_empty() {
+        let tracks = library::tracks()?;
+        select_tracks(&tracks, true)?
+            .iter()
+            .map(Entry::from_track)
+            .collect()
+    } else {
+        entries_for(paths)?
+    };
+    let playlist = Playlist {
+        entries,
+        ..Playlist::default()
+    };
+    let path = write_playlist(name, &playlist)?;
     println!("Created playlist at {}", path.display());
     Ok(())
 }
 
-fn write_playlist(name: &str, files: &[PathBuf]) -> io::Result<PathBuf> {
-    let playlist_path = get_orpheus_dir().join(format!("{}.m3u", name));
-    let mut file = File::create(&playlist_path)?;
+fn write_playlist(name: &str, playlist: &Playlist) -> io::Result<PathBuf> {
+    let playlist_path = data_dir().join(format!("{}.m3u", name));
+    playlist.write(&playlist_path)?;
+    Ok(playlist_path)
+}
+
+pub fn create_smart_playlist(name: &str, query: &str) -> io::Result<()> {
+    query
+        .parse::<Query>()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    ensure_new(name)?;
+    let path = data_dir().join(format!("{}.smart", name));
+    fs::write(&path, format!("{}\n", query))?;
+    println!("Created smart playlist at {}", path.display());
+    Ok(())
+}
 
-    writeln!(file, "#EXTM3U")?;
-    for f in files {
-        writeln!(file, "{}", f.display())?;
+/// Fails if a playlist called `name` exists already.
+fn ensure_new(name: &str) -> io::Result<()> {
+    if find_playlist(name).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("playlist `{}` already exists", name),
+        ));
     }
+    Ok(())
+}
 
-    Ok(playlist_path)
+/// Turns command line arguments into playlist entries. Each one may be a
+/// URL, an audio file, a playlist file or CUE sheet, a directory (expanded
+/// to the tracks below it, sorted by path) or the name of a playlist.
+/// Library tracks carry their tags.
+pub fn entries_for(locations: &[String]) -> io::Result<Vec<Entry>> {
+    let tracks = library::tracks()?;
+    let mut entries = Vec::new();
+    for location in locations {
+        if location.contains("://") {
+            entries.push(Entry {
+                location: location.clone(),
+                ..Entry::new(PathBuf::from(location))
+            });
+            continue;
+        }
+
+        let path = std::path::absolute(location)?;
+        if path.is_dir() {
+            let below = library::tracks_under(&tracks, &path);
+            if !below.is_empty() {
+                entries.extend(below.into_iter().map(Entry::from_track));
+                continue;
+            }
+            // Not part of the library, e.g. a fresh download.
+            let mut files: Vec<_> = walk_audio(std::slice::from_ref(&path))?
+                .files
+                .into_iter()
+                .map(|f| f.path)
+                .filter(|p| is_audio_file(p))
+                .collect();
+            files.sort_by(|a, b| library::natural_cmp(a, b));
+            entries.extend(files.into_iter().map(Entry::new));
+        } else if path.is_file() {
+            if cue::is_cue_sheet(&path) {
+                entries.extend(cue::read(&path)?.into_iter().map(|t| Entry {
+                    segment: Some(Segment {
+                        start: t.start,
+                        end: t.end,
+                    }),
+                    ..Entry::new(t.file)
+                }));
+            } else if is_smart(&path) || Format::from_path(&path).is_some() {
+                entries.extend(load_playlist(&path)?.entries);
+            } else {
+                let matching: Vec<_> = tracks.iter().filter(|t| t.path == path).collect();
+                if matching.is_empty() {
+                    entries.push(Entry::new(path));
+                } else {
+                    entries.extend(matching.into_iter().map(Entry::from_track));
+                }
+            }
+        } else {
+            let playlist = find_playlist(location)
+                .map_err(|_| Error::NotFound(format!("no such file or playlist: {}", location)))?;
+            entries.extend(load_playlist(&playlist)?.entries);
+        }
+    }
+    Ok(entries)
 }
 
-pub fn edit_playlist() -> io::Result<()> {
-    let playlists = list_playlists()?;
-    if playlists.is_empty() {
-        eprintln!("No playlists available.");
-        return Ok(());
+pub fn add_to_playlist(name: &str, paths: &[String]) -> io::Result<()> {
+    let path = find_static_playlist(name)?;
+    let mut playlist = Playlist::read(&path)?;
+    let entries = entries_for(paths)?;
+    println!("Added {} track(s).", entries.len());
+    playlist.entries.extend(entries);
+    playlist.write(&path)
+}
+
+/// Removes the entries whose path or title matches the glob `pattern`.
+pub fn remove_from_playlist(name: &str, pattern: &str) -> io::Result<()> {
+    let path = find_static_playlist(name)?;
+    let mut playlist = Playlist::read(&path)?;
+    let before = playlist.entries.len();
+    playlist.entries.retain(|e| {
+        !glob::matches(pattern, &e.path.to_string_lossy(), false)
+            && !e
+                .title
+                .as_ref()
+                .is_some_and(|t| glob::matches(pattern, t, false))
+    });
+    println!("Removed {} track(s).", before - playlist.entries.len());
+    playlist.write(&path)
+}
+
+pub fn show_playlist(name: &str) -> io::Result<()> {
+    let playlist = load_playlist(&find_playlist(name)?)?;
+    for (i, entry) in playlist.entries.iter().enumerate() {
+        println!(
+            "{:>3}  {:>7}  {}",
+            i + 1,
+            format_time(entry.duration),
+            entry.label()
+        );
     }
+    Ok(())
+}
 
-    let selected_playlist = run_fzf(&playlists, false)?;
+pub fn rename_playlist(old: &str, new: &str) -> io::Result<()> {
+    let path = find_playlist(old)?;
+    ensure_new(new)?;
+    // Keep the extension so .m3u8 playlists stay UTF-8. Appended rather
+    // than set, as names may contain dots (`vol.2`).
+    let destination = match path.extension() {
+        Some(extension) => data_dir().join(format!("{}.{}", new, extension.to_string_lossy())),
+        None => data_dir().join(new),
+    };
+    fs::rename(&path, &destination)?;
+    println!("Renamed playlist to {}", destination.display());
+    Ok(())
+}
+
+pub fn copy_playlist(from: &str, to: &str) -> io::Result<()> {
+    let source = find_playlist(from)?;
+    ensure_new(to)?;
+    let path = if is_smart(&source) {
+        let path = data_dir().join(format!("{}.smart", to));
+        fs::copy(&source, &path)?;
+        path
+    } else {
+        write_playlist(to, &Playlist::read(&source)?)?
+    };
+    println!("Copied playlist to {}", path.display());
+    Ok(())
+}
+
+/// Edits the named playlist, or one selected in fzf.
+pub fn edit_playlist(name: Option<&str>) -> io::Result<()> {
+    let selected_playlist = match name {
+        Some(name) => vec![find_playlist(name)?],
+        None => {
+            let playlists = list_playlists()?;
+            if playlists.is_empty() {
+                return Err(Error::NotFound("no playlists available".into()).into());
+            }
+            run_fzf(&playlists, false)?
+        }
+    };
     if selected_playlist.is_empty() {
-        return Ok(());
+        return Err(Error::Cancelled.into());
     }
     let playlist_path = &selected_playlist[0];
-    let playlist_name = playlist_path.file_stem().unwrap().to_string_lossy();
+    if is_smart(playlist_path) {
+        return Err(Error::Usage(format!(
+            "smart playlists are defined by a query; edit {} instead",
+            playlist_path.display()
+        ))
+        .into());
+    }
 
-    let actions = vec!["delete", "append"];
+    let actions = ["delete", "append", "move", "insert-at", "sort", "reverse"];
     let action_selected = run_fzf(
-        &actions.iter().map(|s| PathBuf::from(s)).collect::<Vec<_>>(),
+        &actions.iter().map(PathBuf::from).collect::<Vec<_>>(),
         false,
     )?;
     if action_selected.is_empty() {
-        return Ok(());
+        return Err(Error::Cancelled.into());
     }
 
-    let mut playlist_tracks: Vec<PathBuf> = {
-        let file = File::open(playlist_path)?;
-        BufReader::new(file)
-            .lines()
-            .filter_map(|l| l.ok())
-            .filter(|l| !l.starts_with('#'))
-            .map(PathBuf::from)
-            .collect()
-    };
+    let mut playlist = Playlist::read(playlist_path)?;
 
     match action_selected[0].to_string_lossy().as_ref() {
         "delete" => {
-            let to_delete = run_fzf(&playlist_tracks, true)?;
-            playlist_tracks.retain(|f| !to_delete.contains(f));
+            let labels: Vec<_> = playlist.entries.iter().map(Entry::label).collect();
+            let to_delete = run_fzf_labeled(&labels, true)?;
+            let mut index = 0;
+            playlist.entries.retain(|_| {
+                index += 1;
+                !to_delete.contains(&(index - 1))
+            });
             println!("Deleted {} track(s).", to_delete.len());
         }
 
         "append" => {
-            let music_files = scan_music()?;
-            let to_append_candidates: Vec<_> = music_files
+            let to_append_candidates: Vec<_> = library::tracks()?
                 .into_iter()
-                .filter(|f| !playlist_tracks.contains(f))
+                .filter(|t| !playlist.entries.iter().any(|e| e.is_track(t)))
                 .collect();
 
             if to_append_candidates.is_empty() {
                 println!("No new tracks available to append.");
             } else {
-                let to_append = run_fzf(&to_append_candidates, true)?;
-                playlist_tracks.extend(to_append);
-                println!("Appended {} track(s).", playlist_tracks.len());
+                let to_append = select_tracks(&to_append_candidates, true)?;
+                playlist
+                    .entries
+                    .extend(to_append.iter().map(Entry::from_track));
+                println!("Appended {} track(s).", to_append.len());
+            }
+        }
+
+        "move" => {
+            let labels: Vec<_> = playlist.entries.iter().map(Entry::label).collect();
+            let to_move = run_fzf_labeled(&labels, true)?;
+            if to_move.is_empty() {
+                return Err(Error::Cancelled.into());
             }
+            let target = pick_position(&playlist)?;
+
+            let (mut moved, mut rest) = (Vec::new(), Vec::new());
+            for (i, entry) in playlist.entries.drain(..).enumerate() {
+                if to_move.contains(&i) {
+                    moved.push(entry);
+                } else {
+                    rest.push(entry);
+                }
+            }
+            // The target index counts the moved entries too.
+            let at = (0..target).filter(|i| !to_move.contains(i)).count();
+            let count = moved.len();
+            rest.splice(at..at, moved);
+            playlist.entries = rest;
+            println!("Moved {} track(s).", count);
+        }
+
+        "insert-at" => {
+            let at = pick_position(&playlist)?;
+            let to_insert = select_tracks(&library::tracks()?, true)?;
+            playlist
+                .entries
+                .splice(at..at, to_insert.iter().map(Entry::from_track));
+            println!("Inserted {} track(s).", to_insert.len());
+        }
+
+        "sort" => {
+            let keys = SORT_KEYS.map(String::from);
+            let Some(&key) = run_fzf_labeled(&keys, false)?.first() else {
+                return Err(Error::Cancelled.into());
+            };
+            sort_entries(&mut playlist.entries, SORT_KEYS[key], &library::tracks()?);
+            println!("Sorted by {}.", SORT_KEYS[key]);
+        }
+
+        "reverse" => {
+            playlist.entries.reverse();
+            println!("Reversed {} track(s).", playlist.entries.len());
         }
 
         _ => {}
     }
 
-    write_playlist(&playlist_name, &playlist_tracks)?;
+    playlist.write(playlist_path)?;
     Ok(())
 }
 
-pub fn delete_playlists() -> io::Result<()> {
-    let playlists = list_playlists()?;
-    if playlists.is_empty() {
-        eprintln!("No playlists available to delete.");
-        return Ok(());
+/// Lets the user pick where to put tracks: before one of the entries or at
+/// the end. Returns the index to insert at.
+fn pick_position(playlist: &Playlist) -> io::Result<usize> {
+    let mut labels: Vec<_> = playlist
+        .entries
+        .iter()
+        .enumerate()
+        .map(|(i, e)| format!("before {:>3}. {}", i + 1, e.label()))
+        .collect();
+    labels.push("at the end".to_string());
+    first_choice(run_fzf_labeled(&labels, false)?)
+}
+
+fn first_choice(choices: Vec<usize>) -> io::Result<usize> {
+    choices
+        .first()
+        .copied()
+        .ok_or_else(|| Error::Cancelled.into())
+}
+
+const SORT_KEYS: [&str; 7] = [
+    "path",
+    "title",
+    "artist",
+    "album",
+    "track number",
+    "duration",
+    "date added",
+];
+
+#[derive(PartialEq, PartialOrd)]
+enum SortValue {
+    Text(String),
+    Number(f64),
+}
+
+/// Stable sort by one of `SORT_KEYS`. Tags come from the library, falling back
+/// to the playlist's own directives; entries without a value go last.
+fn sort_entries(entries: &mut Vec<Entry>, key: &str, tracks: &[Track]) {
+    let mut by_path: HashMap<&Path, Vec<&Track>> = HashMap::new();
+    for track in tracks {
+        by_path.entry(&track.path).or_default().push(track);
     }
 
-    let selected = run_fzf(&playlists, true)?;
+    let text = |s: Option<&String>| s.map(|s| SortValue::Text(s.to_lowercase()));
+    let mut keyed: Vec<_> = entries
+        .drain(..)
+        .map(|entry| {
+            let track = by_path
+                .get(entry.path.as_path())
+                .and_then(|t| t.iter().find(|t| entry.is_track(t)));
+            let tags = track.map(|t| &t.tags);
+            let value = match key {
+                "path" => Some(SortValue::Text(entry.path.to_string_lossy().into_owned())),
+                "title" => text(tags.and_then(|t| t.title.as_ref()).or(entry.title.as_ref())),
+                "artist" => text(
+                    tags.and_then(|t| t.artist.as_ref())
+                        .or(entry.artist.as_ref()),
+                ),
+                "album" => text(tags.and_then(|t| t.album.as_ref()).or(entry.album.as_ref())),
+                "track number" => tags
+                    .and_then(|t| t.track)
+                    .map(|n| SortValue::Number(n.into())),
+                "duration" => entry
+                    .duration
+                    .or(track.and_then(|t| t.duration))
+                    .map(SortValue::Number),
+                _ => track.map(|t| SortValue::Number(t.added as f64)),
+            };
+            (value, entry)
+        })
+        .collect();
+
+    keyed.sort_by(|(a, _), (b, _)| match (a, b) {
+        (Some(a), Some(b)) => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    });
+    entries.extend(keyed.into_iter().map(|(_, entry)| entry));
+}
+
+/// Replaces the mpv queue with the playlist. mpv's own playlist loader
+/// ignores `#EXTVLCOPT` and knows nothing of smart playlists, so those are
+/// queued entry by entry.
+pub fn play_playlist(path: &Path) -> io::Result<()> {
+    let playlist = load_playlist(path)?;
+    if !is_smart(path) && playlist.entries.iter().all(|e| e.segment.is_none()) {
+        return send_command(MpvCommand::LoadPlaylist {
+            path: path_arg(path)?,
+        });
+    }
+    if playlist.entries.is_empty() {
+        return Err(io::Error::other(format!(
+            "{} matches no tracks",
+            path.display()
+        )));
+    }
+    enqueue(&playlist.entries, true)
+}
+
+/// Queues the entries in order, replacing the current queue if `replace`.
+pub fn enqueue(entries: &[Entry], replace: bool) -> io::Result<()> {
+    if entries.is_empty() {
+        return Err(io::Error::other("no tracks to queue"));
+    }
+    // Checked up front, so a bad path doesn't leave the queue half replaced.
+    let commands = entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| entry.enqueue_command(replace && i == 0))
+        .collect::<io::Result<Vec<_>>>()?;
+    let mut client = Client::connect()?;
+    for command in &commands {
+        client.request(command)?;
+    }
+    Ok(())
+}
+
+/// Queues the entries right after the current track, in order. Without a
+/// current track they are appended.
+pub fn enqueue_next(entries: &[Entry]) -> io::Result<()> {
+    if entries.is_empty() {
+        return Err(io::Error::other("no tracks to queue"));
+    }
+    let queue = get_queue()?;
+    let Some(current) = queue.iter().position(|e| e.current) else {
+        return enqueue(entries, false);
+    };
+    let commands = entries
+        .iter()
+        .map(|entry| entry.enqueue_command(false))
+        .collect::<io::Result<Vec<_>>>()?;
+    // Appended, then moved into place, as loadfile's insert-next needs mpv 0.38.
+    let mut client = Client::connect()?;
+    for (i, command) in commands.iter().enumerate() {
+        client.request(command)?;
+        client.request(&MpvCommand::MoveEntry {
+            from: queue.len() + i,
+            to: current + 1 + i,
+        })?;
+    }
+    Ok(())
+}
+
+/// Lets the user pick albums or artists and returns their tracks in order.
+pub fn select_group(grouping: Grouping) -> io::Result<Vec<Entry>> {
+    let groups = library::group(&library::tracks()?, grouping);
+    let labels: Vec<_> = groups.iter().map(|g| g.label.clone()).collect();
+    Ok(run_fzf_labeled(&labels, true)?
+        .into_iter()
+        .flat_map(|i| &groups[i].tracks)
+        .map(Entry::from_track)
+        .collect())
+}
+
+/// Lets the user pick directories under the music roots and returns the
+/// tracks below them in natural order.
+pub fn select_folder() -> io::Result<Vec<Entry>> {
+    let tracks = library::tracks()?;
+    let mut dirs: Vec<(&Path, &Path)> = Vec::new();
+    let mut seen = HashSet::new();
+    for track in &tracks {
+        for dir in track.path.ancestors().skip(1) {
+            if !dir.starts_with(&track.root) || !seen.insert(dir) {
+                break;
+            }
+            dirs.push((dir, &track.root));
+        }
+    }
+    dirs.sort_by(|a, b| library::natural_cmp(a.0, b.0));
+
+    // Shown with the root's own name, e.g. `Music/Jazz/Coltrane`.
+    let labels: Vec<_> = dirs
+        .iter()
+        .map(|(dir, root)| {
+            let base = root.parent().unwrap_or(root);
+            dir.strip_prefix(base).unwrap_or(dir).display().to_string()
+        })
+        .collect();
+    Ok(run_fzf_labeled(&labels, true)?
+        .into_iter()
+        .flat_map(|i| library::tracks_under(&tracks, dirs[i].0))
+        .map(Entry::from_track)
+        .collect())
+}
+
+/// The library tracks selected by a `--query` argument.
+pub fn query_tracks(query: &str) -> io::Result<Vec<Track>> {
+    let query: Query = query
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let tracks = query.run(&library::tracks()?);
+    if tracks.is_empty() {
+        return Err(io::Error::other("query matches no tracks"));
+    }
+    Ok(tracks)
+}
+
+/// Deletes the named playlists, or the ones selected in fzf.
+pub fn delete_playlists(names: &[String]) -> io::Result<()> {
+    let selected = if names.is_empty() {
+        let playlists = list_playlists()?;
+        if playlists.is_empty() {
+            return Err(Error::NotFound("no playlists available to delete".into()).into());
+        }
+        run_fzf(&playlists, true)?
+    } else {
+        names
+            .iter()
+            .map(|name| find_playlist(name))
+            .collect::<io::Result<Vec<_>>>()?
+    };
     if selected.is_empty() {
-        return Ok(());
+        return Err(Error::Cancelled.into());
     }
 
+    // Deletes what it can, then fails if anything couldn't be deleted.
+    let mut failed = 0;
     for playlist_path in &selected {
         if let Err(e) = fs::remove_file(playlist_path) {
             eprintln!("Failed to delete {}: {}", playlist_path.display(), e);
+            failed += 1;
         }
     }
+    if failed > 0 {
+        return Err(io::Error::other(format!(
+            "failed to delete {} of {} playlist(s)",
+            failed,
+            selected.len()
+        )));
+    }
 
     Ok(())
 }
 
-pub fn scan_music() -> io::Result<Vec<PathBuf>> {
+pub fn is_audio_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|ext| matches!(ext.to_lowercase().as_str(), "mp3" | "flac" | "ogg" | "wav"))
+}
+
+/// Lists the audio files and CUE sheets under all music roots. Directories
+/// that can't be read are reported on stderr and skipped.
+pub fn scan_music() -> io::Result<Walk> {
+    let config = CONFIG.get().expect("config not initialized");
+    walk_audio(&config.music_dirs)
+}
+
+/// Lists the audio files and CUE sheets under `roots`, honoring the
+/// configured excludes.
+fn walk_audio(roots: &[PathBuf]) -> io::Result<Walk> {
     let config = CONFIG.get().expect("config not initialized");
-    let music_dir = &config.music_dir;
 
-    let mut files = Vec::new();
-    scan_dir(music_dir, &mut files)?;
-    Ok(files)
+    let walk = walk::walk(
+        roots,
+        &WalkOptions {
+            exclude: &config.exclude,
+            follow_symlinks: config.follow_symlinks,
+            threads: config.scan_threads,
+        },
+    );
+    for warning in &walk.warnings {
+        eprintln!("warning: {}", warning);
+    }
+
+    Ok(Walk {
+        files: walk
+            .files
+            .into_iter()
+            .filter(|f| is_audio_file(&f.path) || cue::is_cue_sheet(&f.path))
+            .collect(),
+        ..walk
+    })
 }
 
-fn scan_dir(dir: &std::path::Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
-    for entry in std::fs::read_dir(dir)? {
-        let entry = entry?;
-        let path = entry.path();
+/// Labels for the mpv queue: mpv's title, else the library tags, else the
+/// file name.
+fn queue_labels(queue: &[QueueEntry]) -> io::Result<Vec<String>> {
+    let entries = queue_entries(queue)?;
+    Ok(queue
+        .iter()
+        .zip(entries)
+        .map(|(q, e)| q.title.clone().unwrap_or_else(|| e.label()))
+        .collect())
+}
 
-        if path.is_dir() {
-            scan_dir(&path, files)?;
-        } else if path.is_file() {
-            if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-                match ext.to_lowercase().as_str() {
-                    "mp3" | "flac" | "ogg" | "wav" => files.push(path),
-                    _ => {}
-                }
-            }
-        }
+/// The mpv queue as playlist entries. Files the library knows carry its tags,
+/// unless several tracks share the file (CUE images), since mpv doesn't report
+/// which part of it an entry plays.
+fn queue_entries(queue: &[QueueEntry]) -> io::Result<Vec<Entry>> {
+    let tracks = library::tracks()?;
+    let mut by_path: HashMap<&Path, Vec<&Track>> = HashMap::new();
+    for track in &tracks {
+        by_path.entry(&track.path).or_default().push(track);
     }
-    Ok(())
+    Ok(queue
+        .iter()
+        .map(
+            |q| match by_path.get(Path::new(&q.filename)).map(Vec::as_slice) {
+                Some([track]) => Entry::from_track(track),
+                _ => Entry::new(PathBuf::from(&q.filename)),
+            },
+        )
+        .collect())
 }
 
-pub fn jump() -> io::Result<Option<usize>> {
+fn current_queue() -> io::Result<Vec<QueueEntry>> {
     let queue = get_queue()?;
-
     if queue.is_empty() {
-        return Err(io::Error::new(io::ErrorKind::Other, "queue is empty"));
+        return Err(io::Error::other("queue is empty"));
     }
+    Ok(queue)
+}
 
-    let selected = run_fzf(
-        &queue.iter().map(|s| PathBuf::from(s)).collect::<Vec<_>>(),
-        false,
-    )?;
+/// Checks a 1-based queue position from the command line, returning the index.
+fn queue_index(queue: &[QueueEntry], position: usize) -> io::Result<usize> {
+    if position == 0 || position > queue.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "no track {} in the queue ({} tracks)",
+                position,
+                queue.len()
+            ),
+        ));
+    }
+    Ok(position - 1)
+}
 
-    if selected.is_empty() {
-        return Ok(None);
+pub fn jump() -> io::Result<usize> {
+    let queue = current_queue()?;
+    first_choice(run_fzf_labeled(&queue_labels(&queue)?, false)?)
+}
+
+pub fn list_queue() -> io::Result<()> {
+    let queue = get_queue()?;
+    if queue.is_empty() {
+        println!("Queue is empty.");
+        return Ok(());
+    }
+    for (i, (entry, label)) in queue.iter().zip(queue_labels(&queue)?).enumerate() {
+        let marker = if entry.current { '>' } else { ' ' };
+        println!("{} {:>3}  {}", marker, i + 1, label);
     }
+    Ok(())
+}
 
-    let chosen_filename = selected[0].to_string_lossy().to_string();
-    if let Some(index) = queue.iter().position(|f| *f == chosen_filename) {
-        Ok(Some(index))
+/// Removes the tracks at the given 1-based positions, or the ones picked in
+/// fzf when none are given.
+pub fn remove_from_queue(positions: &[usize]) -> io::Result<()> {
+    let queue = current_queue()?;
+    let mut indices = if positions.is_empty() {
+        run_fzf_labeled(&queue_labels(&queue)?, true)?
     } else {
-        Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!("failed to find the index of {}", chosen_filename),
-        ))
+        positions
+            .iter()
+            .map(|&p| queue_index(&queue, p))
+            .collect::<io::Result<_>>()?
+    };
+    // From the back, so the remaining indices stay valid.
+    indices.sort_unstable_by(|a, b| b.cmp(a));
+    indices.dedup();
+
+    let mut client = Client::connect()?;
+    for &index in &indices {
+        client.request(&MpvCommand::RemoveEntry { index })?;
+    }
+    println!("Removed {} track(s).", indices.len());
+    Ok(())
+}
+
+/// Moves the track at position `from` to position `to`, both 1-based.
+pub fn move_in_queue(from: usize, to: usize) -> io::Result<()> {
+    let queue = current_queue()?;
+    let from = queue_index(&queue, from)?;
+    let to = queue_index(&queue, to)?;
+    // playlist-move puts the entry before the target, so moving down has to
+    // aim one past it.
+    let to = if to > from { to + 1 } else { to };
+    send_command(MpvCommand::MoveEntry { from, to })
+}
+
+/// Empties the queue. mpv's playlist-clear always keeps the current track, so
+/// it is removed separately unless `keep_current`.
+pub fn clear_queue(keep_current: bool) -> io::Result<()> {
+    let has_current = get_queue()?.iter().any(|e| e.current);
+    let mut client = Client::connect()?;
+    client.request(&MpvCommand::ClearQueue)?;
+    if has_current && !keep_current {
+        client.request(&MpvCommand::RemoveEntry { index: 0 })?;
+    }
+    Ok(())
+}
+
+/// Saves the live mpv queue as a new playlist.
+pub fn save_queue(name: &str) -> io::Result<()> {
+    ensure_new(name)?;
+    let playlist = Playlist {
+        entries: queue_entries(&current_queue()?)?,
+        ..Playlist::default()
+    };
+    let path = write_playlist(name, &playlist)?;
+    println!(
+        "Saved {} track(s) to {}",
+        playlist.entries.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(content: &str) -> String {
+        Playlist::parse(content, Path::new("/music")).to_string()
+    }
+
+    #[test]
+    fn m3u_round_trip() {
+        let content = "#EXTM3U\n\
+            #PLAYLIST:Jazz\n\
+            #EXTENC:UTF-8\n\
+            #EXTGRP:Bebop\n\
+            #EXTINF:312 tvg-id=\"x\",John Coltrane - Blue Train\n\
+            #EXTALB:Blue Train\n\
+            #EXTART:John Coltrane\n\
+            Coltrane/01.flac\n\
+            http://radio.example/stream\n\
+            #EXTINF:180.500,Side A\n\
+            #EXTVLCOPT:start-time=0\n\
+            #EXTVLCOPT:stop-time=180.5\n\
+            /music/image.flac\n\
+            #EXTGRP:trailing\n";
+        assert_eq!(round_trip(content), content);
+    }
+
+    #[test]
+    fn m3u_resolves_relative_entries() {
+        let playlist = Playlist::parse(
+            "a/01.flac\n/abs/02.flac\nfile:///x%20y.flac\n",
+            Path::new("/music"),
+        );
+        let paths: Vec<_> = playlist.entries.iter().map(|e| e.path.clone()).collect();
+        assert_eq!(
+            paths,
+            [
+                PathBuf::from("/music/a/01.flac"),
+                PathBuf::from("/abs/02.flac"),
+                PathBuf::from("/x y.flac"),
+            ]
+        );
+        assert_eq!(playlist.entries[0].location, "a/01.flac");
+    }
+
+    #[test]
+    fn unknown_vlc_options_are_kept_verbatim() {
+        let content = "#EXTM3U\n#EXTVLCOPT:network-caching=1000\nhttp://radio.example/stream\n";
+        let playlist = Playlist::parse(content, Path::new("/music"));
+        assert!(playlist.entries[0].segment.is_none());
+        assert_eq!(playlist.to_string(), content);
+    }
+
+    #[test]
+    fn vlc_options_make_a_segment() {
+        let playlist = Playlist::parse(
+            "#EXTVLCOPT:start-time=61.5\n#EXTVLCOPT:stop-time=120\nimage.flac\n",
+            Path::new("/music"),
+        );
+        let segment = playlist.entries[0].segment.as_ref().unwrap();
+        assert_eq!((segment.start, segment.end), (61.5, Some(120.0)));
     }
 }
@@ -0,0 +1,573 @@
+use std::cmp::Ordering;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::library::Track;
+use crate::regex::Regex;
+
+/// A filter over library tracks, e.g. `genre=jazz AND year>=1960 AND year<1970`
+/// or `artist~coltrane sort:year limit:20`.
+///
+/// Conditions are `<field><op><value>`, combined with `AND` (or just
+/// whitespace), `OR`, `NOT` and parentheses. `sort:<field>[,<field>...]`
+/// (`-field` for descending) and `limit:<n>` apply to the result.
+#[derive(Debug, Clone)]
+pub struct Query {
+    filter: Option<Expr>,
+    sort: Vec<(Field, bool)>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    Not(Box<Expr>),
+    Condition(Condition),
+}
+
+#[derive(Debug, Clone)]
+struct Condition {
+    field: Field,
+    op: Op,
+    value: Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Artist,
+    Album,
+    Title,
+    Genre,
+    Path,
+    Year,
+    Track,
+    Duration,
+    /// When the track was first indexed.
+    Added,
+    /// Any directory the track is in, matched by trailing components.
+    Dir,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    /// Substring, or regex for `/.../` values.
+    Contains,
+    NotContains,
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Text(String),
+    Number(f64),
+    Regex(Regex),
+}
+
+impl FromStr for Field {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "artist" => Ok(Field::Artist),
+            "album" => Ok(Field::Album),
+            "title" => Ok(Field::Title),
+            "genre" => Ok(Field::Genre),
+            "path" => Ok(Field::Path),
+            "year" => Ok(Field::Year),
+            "track" => Ok(Field::Track),
+            "duration" => Ok(Field::Duration),
+            "added" => Ok(Field::Added),
+            "dir" => Ok(Field::Dir),
+            _ => Err(format!("unknown field `{}`", s)),
+        }
+    }
+}
+
+impl Field {
+    fn is_text(self) -> bool {
+        matches!(
+            self,
+            Field::Artist | Field::Album | Field::Title | Field::Genre | Field::Path
+        )
+    }
+
+    fn parse_value(self, value: &str) -> Result<Value, String> {
+        let invalid = || format!("invalid value `{}` for {:?}", value, self);
+        match self {
+            Field::Dir => Ok(Value::Text(value.trim_end_matches('/').to_string())),
+            _ if self.is_text() => Ok(Value::Text(value.to_lowercase())),
+            Field::Duration => parse_duration(value).map(Value::Number).ok_or_else(invalid),
+            Field::Added => parse_date(value).map(Value::Number).ok_or_else(invalid),
+            _ => value.parse().map(Value::Number).map_err(|_| invalid()),
+        }
+    }
+
+    fn of(self, track: &Track) -> Option<Value> {
+        let text = |s: &Option<String>| s.as_ref().map(|s| Value::Text(s.to_lowercase()));
+        match self {
+            Field::Artist => text(&track.tags.artist),
+            Field::Album => text(&track.tags.album),
+            Field::Title => text(&track.tags.title),
+            Field::Genre => text(&track.tags.genre),
+            Field::Path => Some(Value::Text(track.path.to_string_lossy().to_lowercase())),
+            Field::Year => track.tags.year.map(|y| Value::Number(y.into())),
+            Field::Track => track.tags.track.map(|n| Value::Number(n.into())),
+            Field::Duration => track.duration.map(Value::Number),
+            Field::Added => Some(Value::Number(track.added as f64)),
+            Field::Dir => None,
+        }
+    }
+}
+
+/// `ss`, `mm:ss` or `hh:mm:ss`.
+fn parse_duration(s: &str) -> Option<f64> {
+    s.split(':').try_fold(0.0, |secs, part| {
+        Some(secs * 60.0 + part.parse::<f64>().ok()?)
+    })
+}
+
+/// `YYYY-MM-DD`, or an age like `30d`, `6w`, `3m` or `1y` meaning that long
+/// ago, so `added>=30d` is "added in the last 30 days". Returns a Unix time.
+fn parse_date(s: &str) -> Option<f64> {
+    if let Some(count) = s.strip_suffix(['d', 'w', 'm', 'y']) {
+        let days = match s.chars().last() {
+            Some('d') => 1.0,
+            Some('w') => 7.0,
+            Some('m') => 30.0,
+            _ => 365.0,
+        };
+        let count: f64 = count.parse().ok()?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or_default();
+        return Some(now - count * days * 86400.0);
+    }
+
+    let mut parts = s.split('-').map(|p| p.parse::<i64>().ok());
+    let (y, m, d) = (parts.next()??, parts.next()??, parts.next()??);
+    if parts.next().is_some() || !(1..=12).contains(&m) || !(1..=31).contains(&d) {
+        return None;
+    }
+    Some((days_from_civil(y, m, d) * 86400) as f64)
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((m + 9) % 12) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Word(String),
+}
+
+/// Splits on whitespace outside double quotes; quotes are removed. Unquoted
+/// parentheses at the start or end of a word are tokens of their own.
+fn tokens(s: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    let mut started = false;
+    // Unquoted `)` at the end of the current word.
+    let mut closing = 0;
+
+    let flush = |word: &mut String, closing: &mut usize, tokens: &mut Vec<Token>| {
+        word.truncate(word.len() - *closing);
+        if !word.is_empty() || *closing == 0 {
+            tokens.push(Token::Word(std::mem::take(word)));
+        }
+        tokens.extend((0..*closing).map(|_| Token::Close));
+        *closing = 0;
+    };
+
+    for c in s.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                started = true;
+                closing = 0;
+            }
+            '(' if !quoted && !started => tokens.push(Token::Open),
+            c if c.is_whitespace() && !quoted => {
+                if started {
+                    flush(&mut word, &mut closing, &mut tokens);
+                    started = false;
+                }
+            }
+            c => {
+                word.push(c);
+                started = true;
+                closing = if c == ')' && !quoted { closing + 1 } else { 0 };
+            }
+        }
+    }
+    if started {
+        flush(&mut word, &mut closing, &mut tokens);
+    }
+    tokens
+}
+
+fn parse_condition(word: &str) -> Result<Condition, String> {
+    let start = word
+        .find(['=', '!', '<', '>', '~'])
+        .ok_or_else(|| format!("expected <field><op><value>, got `{}`", word))?;
+    let (field, rest) = word.split_at(start);
+    let (op, value) = [
+        ("!=", Op::Ne),
+        ("!~", Op::NotContains),
+        ("<=", Op::Le),
+        (">=", Op::Ge),
+        ("=", Op::Eq),
+        ("<", Op::Lt),
+        (">", Op::Gt),
+        ("~", Op::Contains),
+    ]
+    .iter()
+    .find_map(|(symbol, op)| Some((*op, rest.strip_prefix(symbol)?)))
+    .ok_or_else(|| format!("invalid operator in `{}`", word))?;
+
+    let field: Field = field.parse()?;
+    let contains = matches!(op, Op::Contains | Op::NotContains);
+    if field == Field::Dir && !matches!(op, Op::Eq | Op::Ne) {
+        return Err("dir only supports = and !=".to_string());
+    }
+    if contains && !field.is_text() {
+        return Err(format!("{:?} doesn't support ~", field));
+    }
+
+    let value = match value.strip_prefix('/').and_then(|v| v.strip_suffix('/')) {
+        Some(pattern) if contains => {
+            Value::Regex(Regex::new(pattern).map_err(|e| format!("in `{}`: {}", word, e))?)
+        }
+        _ => field.parse_value(value)?,
+    };
+    Ok(Condition { field, op, value })
+}
+
+/// Recursive descent over the tokens; `NOT` binds tighter than `AND`, which
+/// binds tighter than `OR`.
+struct Parser {
+    tokens: std::iter::Peekable<std::vec::IntoIter<Token>>,
+    sort: Vec<(Field, bool)>,
+    limit: Option<usize>,
+}
+
+fn is_keyword(token: Option<&Token>, keyword: &str) -> bool {
+    matches!(token, Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword))
+}
+
+impl Parser {
+    fn or(&mut self) -> Result<Option<Expr>, String> {
+        let mut alts = Vec::new();
+        alts.extend(self.and()?);
+        while is_keyword(self.tokens.peek(), "or") {
+            self.tokens.next();
+            alts.push(self.and()?.ok_or("expected a condition after OR")?);
+        }
+        Ok(match alts.len() {
+            0 | 1 => alts.pop(),
+            _ => Some(Expr::Or(alts)),
+        })
+    }
+
+    fn and(&mut self) -> Result<Option<Expr>, String> {
+        let mut all = Vec::new();
+        loop {
+            match self.tokens.peek() {
+                None | Some(Token::Close) => break,
+                token if is_keyword(token, "or") => break,
+                token if is_keyword(token, "and") => {
+                    self.tokens.next();
+                }
+                _ => all.extend(self.not()?),
+            }
+        }
+        Ok(match all.len() {
+            0 | 1 => all.pop(),
+            _ => Some(Expr::And(all)),
+        })
+    }
+
+    /// A possibly negated condition or group. `sort:` and `limit:` are
+    /// consumed here too and yield no expression.
+    fn not(&mut self) -> Result<Option<Expr>, String> {
+        match self.tokens.next() {
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                if self.tokens.next() != Some(Token::Close) {
+                    return Err("unclosed `(`".to_string());
+                }
+                Ok(Some(expr.ok_or("empty `()`")?))
+            }
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case("not") => {
+                let expr = self.not()?.ok_or("expected a condition after NOT")?;
+                Ok(Some(Expr::Not(Box::new(expr))))
+            }
+            Some(Token::Word(word)) => {
+                if let Some(fields) = word.strip_prefix("sort:") {
+                    for field in fields.split(',') {
+                        let (field, descending) = match field.strip_prefix('-') {
+                            Some(field) => (field, true),
+                            None => (field, false),
+                        };
+                        self.sort.push((field.parse()?, descending));
+                    }
+                    return Ok(None);
+                }
+                if let Some(limit) = word.strip_prefix("limit:") {
+                    let limit = limit
+                        .parse()
+                        .map_err(|_| format!("invalid limit `{}`", limit))?;
+                    self.limit = Some(limit);
+                    return Ok(None);
+                }
+                parse_condition(&word).map(|c| Some(Expr::Condition(c)))
+            }
+            _ => Err("unexpected `)`".to_string()),
+        }
+    }
+}
+
+impl FromStr for Query {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokens(s).into_iter().peekable(),
+            sort: Vec::new(),
+            limit: None,
+        };
+        let filter = parser.or()?;
+        if parser.tokens.next().is_some() {
+            return Err("unexpected `)`".to_string());
+        }
+        if filter.is_none() && parser.sort.is_empty() && parser.limit.is_none() {
+            return Err("empty query".to_string());
+        }
+        Ok(Query {
+            filter,
+            sort: parser.sort,
+            limit: parser.limit,
+        })
+    }
+}
+
+impl Condition {
+    fn matches(&self, track: &Track) -> bool {
+        if self.field == Field::Dir {
+            let Value::Text(dir) = &self.value else {
+                return false;
+            };
+            let under = track
+                .path
+                .ancestors()
+                .skip(1)
+                .any(|a| a.ends_with(Path::new(dir)));
+            return under == (self.op == Op::Eq);
+        }
+
+        // Tracks without the tag never match, not even `!=`.
+        let Some(actual) = self.field.of(track) else {
+            return false;
+        };
+        if let (Op::Contains | Op::NotContains, Value::Text(actual)) = (self.op, &actual) {
+            let found = match &self.value {
+                Value::Regex(regex) => regex.is_match(actual),
+                Value::Text(needle) => actual.contains(needle.as_str()),
+                Value::Number(_) => false,
+            };
+            return found == (self.op == Op::Contains);
+        }
+
+        let Some(ordering) = compare(&actual, &self.value) else {
+            return false;
+        };
+        match self.op {
+            Op::Eq => ordering.is_eq(),
+            Op::Ne => ordering.is_ne(),
+            Op::Lt => ordering.is_lt(),
+            Op::Le => ordering.is_le(),
+            Op::Gt => ordering.is_gt(),
+            Op::Ge => ordering.is_ge(),
+            Op::Contains | Op::NotContains => false,
+        }
+    }
+}
+
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Text(a), Value::Text(b)) => Some(a.cmp(b)),
+        (Value::Number(a), Value::Number(b)) => Some(a.total_cmp(b)),
+        _ => None,
+    }
+}
+
+impl Expr {
+    fn matches(&self, track: &Track) -> bool {
+        match self {
+            Expr::And(all) => all.iter().all(|e| e.matches(track)),
+            Expr::Or(alts) => alts.iter().any(|e| e.matches(track)),
+            Expr::Not(expr) => !expr.matches(track),
+            Expr::Condition(condition) => condition.matches(track),
+        }
+    }
+}
+
+impl Query {
+    /// The matching tracks, in library order unless the query sorts them.
+    pub fn run(&self, tracks: &[Track]) -> Vec<Track> {
+        let mut matched: Vec<&Track> = tracks
+            .iter()
+            .filter(|t| self.filter.as_ref().is_none_or(|f| f.matches(t)))
+            .collect();
+
+        if !self.sort.is_empty() {
+            matched.sort_by(|a, b| {
+                self.sort
+                    .iter()
+                    .map(|&(field, descending)| {
+                        // Tracks without the value go last either way.
+                        let ordering = match (field.of(a), field.of(b)) {
+                            (Some(a), Some(b)) => compare(&a, &b).unwrap_or(Ordering::Equal),
+                            (Some(_), None) => return Ordering::Less,
+                            (None, Some(_)) => return Ordering::Greater,
+                            (None, None) => Ordering::Equal,
+                        };
+                        if descending {
+                            ordering.reverse()
+                        } else {
+                            ordering
+                        }
+                    })
+                    .find(|o| o.is_ne())
+                    .unwrap_or(Ordering::Equal)
+            });
+        }
+
+        matched
+            .into_iter()
+            .take(self.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tags::Tags;
+
+    fn track(path: &str, artist: &str, title: &str, year: i32, duration: f64) -> Track {
+        let tags = Tags {
+            artist: Some(artist.to_string()),
+            title: Some(title.to_string()),
+            year: Some(year),
+            genre: Some("Jazz".to_string()),
+            ..Tags::default()
+        };
+        Track {
+            duration: Some(duration),
+            ..Track::with_tags(path, tags)
+        }
+    }
+
+    fn library() -> Vec<Track> {
+        vec![
+            track(
+                "/music/Coltrane/Blue Train/01.flac",
+                "John Coltrane",
+                "Blue Train",
+                1958,
+                643.0,
+            ),
+            track(
+                "/music/Coltrane/Giant Steps/01.flac",
+                "John Coltrane",
+                "Giant Steps",
+                1960,
+                286.0,
+            ),
+            track(
+                "/music/Davis/Kind of Blue/01.flac",
+                "Miles Davis",
+                "So What",
+                1959,
+                562.0,
+            ),
+            track(
+                "/music/Live/Davis/01.flac",
+                "Miles Davis",
+                "Intro",
+                1970,
+                60.0,
+            ),
+        ]
+    }
+
+    fn titles(query: &str) -> Vec<String> {
+        let query: Query = query.parse().unwrap();
+        query
+            .run(&library())
+            .into_iter()
+            .filter_map(|t| t.tags.title)
+            .collect()
+    }
+
+    #[test]
+    fn conditions() {
+        assert_eq!(
+            titles("artist=\"john coltrane\""),
+            ["Blue Train", "Giant Steps"]
+        );
+        assert_eq!(titles("artist~davis year<1960"), ["So What"]);
+        assert_eq!(titles("genre!=jazz"), Vec::<String>::new());
+        assert_eq!(titles("duration>=10:00"), ["Blue Train"]);
+        assert_eq!(titles("title~/^(intro|outro)$/"), ["Intro"]);
+        assert_eq!(titles("dir=Live/Davis"), ["Intro"]);
+    }
+
+    #[test]
+    fn boolean_operators() {
+        assert_eq!(titles("year=1958 OR year=1970"), ["Blue Train", "Intro"]);
+        assert_eq!(titles("NOT (artist~coltrane OR title=intro)"), ["So What"]);
+        assert_eq!(titles("artist~miles AND NOT year>1960"), ["So What"]);
+    }
+
+    #[test]
+    fn sort_and_limit() {
+        assert_eq!(titles("sort:-year limit:2"), ["Intro", "Giant Steps"]);
+        assert_eq!(
+            titles("artist~coltrane sort:duration"),
+            ["Giant Steps", "Blue Train"]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_queries() {
+        for invalid in [
+            "",
+            "(year=1958",
+            "year=1958)",
+            "color=red",
+            "year>abc",
+            "limit:x",
+        ] {
+            assert!(invalid.parse::<Query>().is_err(), "{}", invalid);
+        }
+    }
+}
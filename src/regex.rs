@@ -0,0 +1,315 @@
+/// A small backtracking regular expression matcher for `~/.../` queries.
+///
+/// Supports literals, `.`, `[a-z]` / `[^a-z]` classes, `\d \w \s` (and their
+/// negations), anchors `^` `$` `\b`, groups `(...)`, alternation `|` and the
+/// quantifiers `* + ? {n} {n,} {n,m}`. Matching is case-insensitive.
+#[derive(Debug, Clone)]
+pub struct Regex {
+    node: Node,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Char(char),
+    Any,
+    Class {
+        negated: bool,
+        items: Vec<ClassItem>,
+    },
+    Start,
+    End,
+    WordBoundary,
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+    },
+}
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Range(char, char),
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match *self {
+            ClassItem::Range(lo, hi) => (lo..=hi).contains(&c),
+            ClassItem::Digit(negated) => c.is_ascii_digit() != negated,
+            ClassItem::Word(negated) => (c.is_alphanumeric() || c == '_') != negated,
+            ClassItem::Space(negated) => c.is_whitespace() != negated,
+        }
+    }
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn alt(&mut self) -> Result<Node, String> {
+        let mut alts = vec![self.concat()?];
+        while self.chars.next_if_eq(&'|').is_some() {
+            alts.push(self.concat()?);
+        }
+        Ok(if alts.len() == 1 {
+            alts.pop().unwrap()
+        } else {
+            Node::Alt(alts)
+        })
+    }
+
+    fn concat(&mut self) -> Result<Node, String> {
+        let mut nodes = Vec::new();
+        while let Some(&c) = self.chars.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.quantifier(atom)?);
+        }
+        Ok(Node::Concat(nodes))
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        let c = self.chars.next().unwrap();
+        Ok(match c {
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '(' => {
+                // Non-capturing groups are the only kind there is.
+                if self.chars.next_if_eq(&'?').is_some() && self.chars.next() != Some(':') {
+                    return Err("unsupported group syntax".to_string());
+                }
+                let node = self.alt()?;
+                if self.chars.next() != Some(')') {
+                    return Err("unclosed group".to_string());
+                }
+                node
+            }
+            '[' => self.class()?,
+            '\\' if self.chars.next_if_eq(&'b').is_some() => Node::WordBoundary,
+            '\\' => match self.escape()? {
+                ClassItem::Range(lo, _) => Node::Char(lo),
+                item => Node::Class {
+                    negated: false,
+                    items: vec![item],
+                },
+            },
+            '*' | '+' | '?' | '{' => return Err(format!("nothing to repeat before `{}`", c)),
+            c => Node::Char(lower(c)),
+        })
+    }
+
+    fn escape(&mut self) -> Result<ClassItem, String> {
+        let c = self.chars.next().ok_or("trailing backslash")?;
+        Ok(match c {
+            'd' | 'D' => ClassItem::Digit(c == 'D'),
+            'w' | 'W' => ClassItem::Word(c == 'W'),
+            's' | 'S' => ClassItem::Space(c == 'S'),
+            'n' => ClassItem::Range('\n', '\n'),
+            't' => ClassItem::Range('\t', '\t'),
+            c => ClassItem::Range(lower(c), lower(c)),
+        })
+    }
+
+    fn class(&mut self) -> Result<Node, String> {
+        let negated = self.chars.next_if_eq(&'^').is_some();
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let c = self.chars.next().ok_or("unclosed character class")?;
+            let item = match c {
+                // A leading `]` is literal.
+                ']' if !first => break,
+                '\\' => self.escape()?,
+                c => ClassItem::Range(lower(c), lower(c)),
+            };
+            first = false;
+            match item {
+                ClassItem::Range(lo, _) if self.chars.peek() == Some(&'-') => {
+                    self.chars.next();
+                    match self.chars.next() {
+                        Some(']') => {
+                            items.extend([item, ClassItem::Range('-', '-')]);
+                            break;
+                        }
+                        Some('\\') => match self.escape()? {
+                            ClassItem::Range(hi, _) => items.push(ClassItem::Range(lo, hi)),
+                            _ => return Err("invalid class range".to_string()),
+                        },
+                        Some(hi) => items.push(ClassItem::Range(lo, lower(hi))),
+                        None => return Err("unclosed character class".to_string()),
+                    }
+                }
+                item => items.push(item),
+            }
+        }
+        Ok(Node::Class { negated, items })
+    }
+
+    fn quantifier(&mut self, node: Node) -> Result<Node, String> {
+        let (min, max) = match self.chars.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                self.chars.next();
+                let mut body = String::new();
+                loop {
+                    match self.chars.next() {
+                        Some('}') => break,
+                        Some(c) => body.push(c),
+                        None => return Err("unclosed `{`".to_string()),
+                    }
+                }
+                let number = |s: &str| {
+                    s.trim()
+                        .parse::<usize>()
+                        .map_err(|_| format!("invalid repetition `{{{}}}`", body))
+                };
+                let bounds = match body.split_once(',') {
+                    None => (number(&body)?, Some(number(&body)?)),
+                    Some((min, "")) => (number(min)?, None),
+                    Some((min, max)) => (number(min)?, Some(number(max)?)),
+                };
+                return Ok(Node::Repeat {
+                    node: Box::new(node),
+                    min: bounds.0,
+                    max: bounds.1,
+                });
+            }
+            _ => return Ok(node),
+        };
+        self.chars.next();
+        Ok(Node::Repeat {
+            node: Box::new(node),
+            min,
+            max,
+        })
+    }
+}
+
+fn lower(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+fn match_node(node: &Node, text: &[char], i: usize, k: &mut dyn FnMut(usize) -> bool) -> bool {
+    match node {
+        Node::Char(c) => text.get(i) == Some(c) && k(i + 1),
+        Node::Any => i < text.len() && k(i + 1),
+        Node::Class { negated, items } => {
+            text.get(i)
+                .is_some_and(|&c| items.iter().any(|item| item.matches(c)) != *negated)
+                && k(i + 1)
+        }
+        Node::Start => i == 0 && k(i),
+        Node::End => i == text.len() && k(i),
+        Node::WordBoundary => {
+            let is_word = |c: Option<&char>| c.is_some_and(|&c| c.is_alphanumeric() || c == '_');
+            let before = i.checked_sub(1).and_then(|i| text.get(i));
+            is_word(before) != is_word(text.get(i)) && k(i)
+        }
+        Node::Concat(nodes) => match_seq(nodes, text, i, k),
+        Node::Alt(alts) => alts.iter().any(|alt| match_node(alt, text, i, k)),
+        Node::Repeat { node, min, max } => match_repeat(node, *min, *max, 0, text, i, k),
+    }
+}
+
+fn match_seq(nodes: &[Node], text: &[char], i: usize, k: &mut dyn FnMut(usize) -> bool) -> bool {
+    match nodes.split_first() {
+        None => k(i),
+        Some((first, rest)) => match_node(first, text, i, &mut |j| match_seq(rest, text, j, k)),
+    }
+}
+
+/// Greedy: tries one more repetition before giving up on the current count.
+fn match_repeat(
+    node: &Node,
+    min: usize,
+    max: Option<usize>,
+    count: usize,
+    text: &[char],
+    i: usize,
+    k: &mut dyn FnMut(usize) -> bool,
+) -> bool {
+    if max.is_none_or(|max| count < max)
+        && match_node(node, text, i, &mut |j| {
+            // Empty repetitions only count towards the minimum, otherwise
+            // `(a*)*` would never terminate.
+            (j != i || count < min) && match_repeat(node, min, max, count + 1, text, j, k)
+        })
+    {
+        return true;
+    }
+    count >= min && k(i)
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, String> {
+        let mut parser = Parser {
+            chars: pattern.chars().peekable(),
+        };
+        let node = parser.alt()?;
+        if parser.chars.next().is_some() {
+            return Err("unmatched `)`".to_string());
+        }
+        Ok(Regex { node })
+    }
+
+    /// Whether the pattern matches anywhere in `text`.
+    pub fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().map(lower).collect();
+        (0..=text.len()).any(|i| match_node(&self.node, &text, i, &mut |_| true))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn matches_anywhere_ignoring_case() {
+        assert!(is_match("train", "Blue Train"));
+        assert!(is_match("^blue", "Blue Train"));
+        assert!(!is_match("^train", "Blue Train"));
+        assert!(is_match("train$", "Blue Train"));
+    }
+
+    #[test]
+    fn alternation_and_groups() {
+        assert!(is_match("^(intro|outro)$", "Outro"));
+        assert!(!is_match("^(intro|outro)$", "Introduction"));
+        assert!(is_match("live (at|in) ", "Live in Japan"));
+    }
+
+    #[test]
+    fn classes_and_quantifiers() {
+        assert!(is_match(r"^\d{4}$", "1959"));
+        assert!(!is_match(r"^\d{4}$", "59"));
+        assert!(is_match(r"^[a-c]+\s\w*$", "Cab Calloway"));
+        assert!(is_match(r"^[^0-9]*$", "No digits"));
+        assert!(is_match("^a.?c$", "ac"));
+        assert!(is_match("^a{2,}$", "aaaa"));
+        assert!(!is_match("^a{2,3}$", "aaaa"));
+        assert!(is_match(r"\blove\b", "All You Need Is Love"));
+        assert!(!is_match(r"\blove\b", "Lovely"));
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        for invalid in ["(", "a)", "[a-", "*a", "a{2"] {
+            assert!(Regex::new(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use crate::config::data_dir;
+use crate::error::Error;
+use crate::json::Json;
+use crate::mpv::{
+    Client, MpvCommand, MpvError, Repeat, SeekTarget, VolumeChange, change_volume, get_queue,
+    get_repeat, set_repeat,
+};
+
+/// How long to wait for the restored track to load before seeking into it.
+const LOAD_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What mpv was doing: the queue, where in it and how it plays. Kept in
+/// `session.json` in the data directory.
+pub struct Session {
+    queue: Vec<String>,
+    /// Zero-based index of the current track.
+    position: Option<usize>,
+    time_pos: Option<f64>,
+    paused: bool,
+    volume: Option<f64>,
+    /// The `shuffle` option as mpv reports it.
+    shuffle: Json,
+    repeat: Repeat,
+}
+
+fn session_file() -> PathBuf {
+    data_dir().join("session.json")
+}
+
+impl Session {
+    /// Reads the current session from mpv.
+    pub fn snapshot() -> io::Result<Session> {
+        let entries = get_queue()?;
+        let mut client = Client::connect()?;
+        let mut property = |name: &str| -> io::Result<Json> {
+            Ok(client.get_optional_property(name)?.unwrap_or(Json::Null))
+        };
+        let position = property("playlist-pos")?
+            .as_i64()
+            .and_then(|p| usize::try_from(p).ok());
+
+        // Names that weren't valid UTF-8 reach us mangled and couldn't be
+        // loaded again, so they are left out and the position follows.
+        let mut queue = Vec::new();
+        let mut new_position = position;
+        for (i, entry) in entries.into_iter().enumerate() {
+            if !entry.filename.contains(char::REPLACEMENT_CHARACTER) {
+                queue.push(entry.filename);
+                continue;
+            }
+            eprintln!("Warning: can't save {}, not valid UTF-8", entry.filename);
+            new_position = match (position, new_position) {
+                (Some(p), Some(new)) if i < p => Some(new - 1),
+                (Some(p), _) if i == p => None,
+                (_, new) => new,
+            };
+        }
+
+        Ok(Session {
+            queue,
+            position: new_position,
+            time_pos: property("time-pos")?.as_f64(),
+            paused: property("pause")? == Json::Bool(true),
+            volume: property("volume")?.as_f64(),
+            shuffle: property("shuffle")?,
+            repeat: get_repeat(&mut client)?,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        fs::write(session_file(), format!("{}\n", self.to_json()))
+    }
+
+    pub fn load() -> io::Result<Session> {
+        let content = fs::read_to_string(session_file()).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => Error::NotFound("no saved session".into()).into(),
+            _ => e,
+        })?;
+        Json::parse(&content)
+            .ok()
+            .and_then(|json| Session::from_json(&json))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid session file {}", session_file().display()),
+                )
+            })
+    }
+
+    /// Replaces mpv's queue and settings with the session's and resumes the
+    /// current track where it was.
+    pub fn restore(&self) -> io::Result<()> {
+        if let Some(volume) = self.volume {
+            change_volume(VolumeChange::Set(volume))?;
+        }
+
+        let mut client = Client::connect()?;
+        if self.shuffle != Json::Null {
+            client.set_property("shuffle", self.shuffle.clone())?;
+        }
+        set_repeat(&mut client, self.repeat)?;
+        if self.queue.is_empty() {
+            return Ok(());
+        }
+
+        // Paused while loading, so the track doesn't play from the top
+        // before the seek.
+        client.set_property("pause", true.into())?;
+        for (i, path) in self.queue.iter().enumerate() {
+            let path = path.clone();
+            client.request(&if i == 0 {
+                MpvCommand::PlayFile {
+                    path,
+                    segment: None,
+                }
+            } else {
+                MpvCommand::AppendFile {
+                    path,
+                    segment: None,
+                }
+            })?;
+        }
+
+        let position = self.position.filter(|&p| p < self.queue.len());
+        if let Some(position) = position {
+            if position > 0 {
+                client.request(&MpvCommand::JumpTo { index: position })?;
+            }
+            if let Some(time_pos) = self.time_pos {
+                if wait_until_loaded(&mut client, position)? {
+                    client.request(&MpvCommand::Seek {
+                        target: SeekTarget::Absolute(time_pos),
+                    })?;
+                } else {
+                    eprintln!("Warning: track took too long to load, not seeking");
+                }
+            }
+        }
+        if !self.paused {
+            client.set_property("pause", false.into())?;
+        }
+        Ok(())
+    }
+
+    fn to_json(&self) -> Json {
+        Json::object([
+            (
+                "queue",
+                Json::Array(self.queue.iter().map(|p| p.as_str().into()).collect()),
+            ),
+            ("position", self.position.into()),
+            ("time_pos", self.time_pos.into()),
+            ("paused", self.paused.into()),
+            ("volume", self.volume.into()),
+            ("shuffle", self.shuffle.clone()),
+            ("repeat", self.repeat.to_string().into()),
+        ])
+    }
+
+    fn from_json(json: &Json) -> Option<Session> {
+        let value = |key: &str| json.get(key).cloned().unwrap_or(Json::Null);
+        Some(Session {
+            queue: json
+                .get("queue")?
+                .as_array()?
+                .iter()
+                .map(|p| p.as_str().map(String::from))
+                .collect::<Option<_>>()?,
+            position: value("position")
+                .as_i64()
+                .and_then(|p| usize::try_from(p).ok()),
+            time_pos: value("time_pos").as_f64(),
+            paused: value("paused") == Json::Bool(true),
+            volume: value("volume").as_f64(),
+            shuffle: value("shuffle"),
+            repeat: value("repeat")
+                .as_str()
+                .and_then(|r| r.parse().ok())
+                .unwrap_or(Repeat::Off),
+        })
+    }
+}
+
+/// Waits until mpv plays the entry at `position` and knows where it is in it.
+/// Returns false on timeout.
+fn wait_until_loaded(client: &mut Client, position: usize) -> Result<bool, MpvError> {
+    let start = Instant::now();
+    while start.elapsed() < LOAD_TIMEOUT {
+        let playing = client
+            .get_optional_property("playlist-playing-pos")?
+            .and_then(|p| p.as_i64());
+        if playing == Some(position as i64) && client.get_optional_property("time-pos")?.is_some() {
+            return Ok(true);
+        }
+        sleep(Duration::from_millis(50));
+    }
+    Ok(false)
+}
@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::library::{self, Track};
+use crate::playlist::Entry;
+
+/// How `--shuffle` reorders tracks before they are queued.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    /// Every track on its own, like mpv's playlist-shuffle.
+    Tracks,
+    /// Albums in random order, each one's tracks in order.
+    Album,
+    /// Random, but never the same artist twice in a row if it can be helped.
+    Artist,
+    /// Random, favouring highly rated and rarely played tracks.
+    Weighted,
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tracks" => Ok(Strategy::Tracks),
+            "album" => Ok(Strategy::Album),
+            "artist" => Ok(Strategy::Artist),
+            "weighted" => Ok(Strategy::Weighted),
+            _ => Err(format!("invalid shuffle mode `{}`", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Shuffle {
+    pub strategy: Strategy,
+    /// The same seed gives the same order for the same tracks.
+    pub seed: Option<u64>,
+}
+
+impl Shuffle {
+    /// Reorders the entries and returns them with the seed used.
+    pub fn apply(&self, entries: Vec<Entry>) -> io::Result<(Vec<Entry>, u64)> {
+        let seed = self.seed.unwrap_or_else(random_seed);
+        let mut rng = Rng(seed);
+        let entries = match self.strategy {
+            Strategy::Tracks => {
+                let mut entries = entries;
+                rng.shuffle(&mut entries);
+                entries
+            }
+            Strategy::Album => album_shuffle(entries, &mut rng),
+            Strategy::Artist => artist_spread(entries, &mut rng),
+            Strategy::Weighted => weighted_shuffle(entries, &library::tracks()?, &mut rng),
+        };
+        Ok((entries, seed))
+    }
+}
+
+fn random_seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default();
+    nanos ^ (std::process::id() as u64) << 32
+}
+
+/// SplitMix64: tiny, and good enough to shuffle music.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform in `0..n`, `n` > 0.
+    fn below(&mut self, n: usize) -> usize {
+        (self.unit() * n as f64) as usize
+    }
+
+    /// Fisher-Yates.
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+/// Splits entries into groups by `key`, keeping their order within a group
+/// and the groups in order of first appearance.
+fn group_by<K: Eq + std::hash::Hash>(
+    entries: Vec<Entry>,
+    key: impl Fn(&Entry) -> K,
+) -> Vec<Vec<Entry>> {
+    let mut index: HashMap<K, usize> = HashMap::new();
+    let mut groups: Vec<Vec<Entry>> = Vec::new();
+    for entry in entries {
+        let i = *index.entry(key(&entry)).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[i].push(entry);
+    }
+    groups
+}
+
+fn album_shuffle(entries: Vec<Entry>, rng: &mut Rng) -> Vec<Entry> {
+    // Keyed like `library::group`: by name and directory, so two albums
+    // called "Greatest Hits" stay apart, and untagged tracks go by directory.
+    let mut albums = group_by(entries, |e| {
+        (
+            e.album.as_deref().unwrap_or_default().to_lowercase(),
+            library::album_dir_of(&e.path).to_path_buf(),
+        )
+    });
+    rng.shuffle(&mut albums);
+    albums.into_iter().flatten().collect()
+}
+
+/// Artists are told apart case-insensitively, "AC/DC" is "Ac/Dc".
+fn artist_key(entry: &Entry) -> Option<String> {
+    entry.artist.as_ref().map(|a| a.to_lowercase())
+}
+
+fn artist_spread(entries: Vec<Entry>, rng: &mut Rng) -> Vec<Entry> {
+    let mut artists = group_by(entries, artist_key);
+    for tracks in &mut artists {
+        rng.shuffle(tracks);
+    }
+
+    let total: usize = artists.iter().map(Vec::len).sum();
+    let mut result = Vec::with_capacity(total);
+    let mut last = None;
+    while result.len() < total {
+        let left = total - result.len();
+        // An artist with more than half of what's left has to go now, or
+        // its tracks end up next to each other at the end.
+        let forced = artists
+            .iter()
+            .position(|t| t.len() * 2 > left && Some(artist_key(&t[0])) != last);
+        let i = forced.unwrap_or_else(|| {
+            let candidates: Vec<_> = (0..artists.len())
+                .filter(|&i| !artists[i].is_empty() && Some(artist_key(&artists[i][0])) != last)
+                .collect();
+            if candidates.is_empty() {
+                // Only the previous artist is left.
+                artists.iter().position(|t| !t.is_empty()).unwrap()
+            } else {
+                // Weighted by what's left, so big artists don't pile up.
+                let weights: usize = candidates.iter().map(|&i| artists[i].len()).sum();
+                let mut pick = rng.below(weights);
+                let mut chosen = candidates[0];
+                for &i in &candidates {
+                    if pick < artists[i].len() {
+                        chosen = i;
+                        break;
+                    }
+                    pick -= artists[i].len();
+                }
+                chosen
+            }
+        });
+        let entry = artists[i].pop().unwrap();
+        last = Some(artist_key(&entry));
+        result.push(entry);
+    }
+    result
+}
+
+/// Weighted random order (Efraimidis-Spirakis): each entry draws
+/// `u^(1/weight)` and the highest draws go first. Unrated tracks count as
+/// average, unknown play counts as never played.
+fn weighted_shuffle(entries: Vec<Entry>, tracks: &[Track], rng: &mut Rng) -> Vec<Entry> {
+    let mut by_path: HashMap<&Path, Vec<&Track>> = HashMap::new();
+    for track in tracks {
+        by_path.entry(&track.path).or_default().push(track);
+    }
+    let weight = |entry: &Entry| {
+        let track = by_path
+            .get(entry.path.as_path())
+            .and_then(|tracks| tracks.iter().find(|t| entry.is_track(t)));
+        let rating = track.and_then(|t| t.tags.rating).unwrap_or(0.5);
+        let plays = track.and_then(|t| t.tags.play_count).unwrap_or(0);
+        (0.5 + rating) / (1.0 + plays as f64).sqrt()
+    };
+
+    let mut keyed: Vec<_> = entries
+        .into_iter()
+        .map(|e| (rng.unit().powf(1.0 / weight(&e)), e))
+        .collect();
+    keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+    keyed.into_iter().map(|(_, e)| e).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn entry(path: &str, artist: &str, album: &str) -> Entry {
+        Entry {
+            artist: Some(artist.to_string()),
+            album: Some(album.to_string()),
+            ..Entry::new(PathBuf::from(path))
+        }
+    }
+
+    #[test]
+    fn album_shuffle_keeps_albums_of_the_same_name_apart() {
+        let entries = vec![
+            entry("/m/A/Greatest Hits/01.flac", "A", "Greatest Hits"),
+            entry("/m/B/Greatest Hits/01.flac", "B", "Greatest Hits"),
+            entry("/m/A/Greatest Hits/02.flac", "A", "Greatest Hits"),
+            entry("/m/B/Greatest Hits/CD2/01.flac", "B", "Greatest Hits"),
+        ];
+        for seed in 0..20 {
+            let shuffled = album_shuffle(entries.clone(), &mut Rng(seed));
+            let artists: Vec<_> = shuffled.iter().map(|e| e.artist.clone().unwrap()).collect();
+            assert!(
+                artists == ["A", "A", "B", "B"] || artists == ["B", "B", "A", "A"],
+                "{:?}",
+                artists
+            );
+        }
+    }
+
+    #[test]
+    fn artist_spread_ignores_case() {
+        let entries = vec![
+            entry("/m/1.flac", "AC/DC", "x"),
+            entry("/m/2.flac", "Ac/Dc", "x"),
+            entry("/m/3.flac", "Queen", "x"),
+            entry("/m/4.flac", "queen", "x"),
+        ];
+        for seed in 0..20 {
+            let shuffled = artist_spread(entries.clone(), &mut Rng(seed));
+            for pair in shuffled.windows(2) {
+                assert_ne!(artist_key(&pair[0]), artist_key(&pair[1]), "seed {}", seed);
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_same_order() {
+        let entries: Vec<_> = (0..10)
+            .map(|i| entry(&format!("/m/{}.flac", i), "A", "x"))
+            .collect();
+        let shuffle = Shuffle {
+            strategy: Strategy::Tracks,
+            seed: Some(42),
+        };
+        let paths = |(entries, _): (Vec<Entry>, u64)| -> Vec<PathBuf> {
+            entries.into_iter().map(|e| e.path).collect()
+        };
+        assert_eq!(
+            paths(shuffle.apply(entries.clone()).unwrap()),
+            paths(shuffle.apply(entries).unwrap())
+        );
+    }
+}
@@ -0,0 +1,830 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Tag metadata of a track. Every field is optional since files are often
+/// only partially tagged.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Tags {
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    pub track: Option<u32>,
+    pub year: Option<i32>,
+    pub genre: Option<String>,
+    /// From 0 (worst) to 1 (best).
+    pub rating: Option<f64>,
+    pub play_count: Option<u32>,
+}
+
+impl Tags {
+    /// Sets a field from a Vorbis-style `KEY=value` comment or an ID3 text
+    /// frame mapped to the same key. Earlier values win.
+    fn set(&mut self, key: &str, value: &str) {
+        let value = value.trim_matches(char::from(0)).trim();
+        if value.is_empty() {
+            return;
+        }
+        match key.to_ascii_uppercase().as_str() {
+            "TITLE" => set_once(&mut self.title, value.to_string()),
+            "ARTIST" => set_once(&mut self.artist, value.to_string()),
+            "ALBUM" => set_once(&mut self.album, value.to_string()),
+            "GENRE" => set_once(&mut self.genre, genre_name(value)),
+            // "3/12" -> 3
+            "TRACKNUMBER" => {
+                if let Some(n) = leading_number(value) {
+                    set_once(&mut self.track, n as u32);
+                }
+            }
+            // "1967-03-01" -> 1967
+            "DATE" | "YEAR" => {
+                if let Some(n) = leading_number(value) {
+                    set_once(&mut self.year, n as i32);
+                }
+            }
+            "FMPS_RATING" => {
+                if let Ok(rating) = value.parse::<f64>() {
+                    set_once(&mut self.rating, rating.clamp(0.0, 1.0));
+                }
+            }
+            // Players write either 1-5 stars or a percentage.
+            "RATING" => {
+                if let Ok(rating) = value.parse::<f64>() {
+                    let scale = if rating <= 5.0 { 5.0 } else { 100.0 };
+                    set_once(&mut self.rating, (rating / scale).clamp(0.0, 1.0));
+                }
+            }
+            // FMPS counts are written as "3.000000".
+            "FMPS_PLAYCOUNT" | "PLAY_COUNT" | "PLAYCOUNT" => {
+                if let Some(n) = leading_number(value) {
+                    set_once(&mut self.play_count, n as u32);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn set_once<T>(field: &mut Option<T>, value: T) {
+    if field.is_none() {
+        *field = Some(value);
+    }
+}
+
+fn leading_number(s: &str) -> Option<u64> {
+    let digits: String = s.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+/// Tags plus stream information read from a file's headers.
+#[derive(Debug, Clone, Default)]
+pub struct Info {
+    pub tags: Tags,
+    /// Length in seconds, when it can be derived from the headers.
+    pub duration: Option<f64>,
+}
+
+/// Reads tags and duration from an MP3, FLAC, Ogg Vorbis/Opus or WAV file.
+/// Unknown or damaged files yield an empty `Info` rather than an error.
+pub fn read(path: &Path) -> io::Result<Info> {
+    let mut file = BufReader::new(File::open(path)?);
+    let size = file.get_ref().metadata()?.len();
+
+    let mut magic = [0u8; 4];
+    if file.read_exact(&mut magic).is_err() {
+        return Ok(Info::default());
+    }
+    file.seek(SeekFrom::Start(0))?;
+
+    let info = match &magic {
+        b"fLaC" => read_flac(&mut file),
+        b"OggS" => read_ogg(&mut file, size),
+        b"RIFF" => read_wav(&mut file),
+        _ => read_mpeg(&mut file, size),
+    };
+
+    // Truncated files are common in download folders; keep what we got.
+    match info {
+        Ok(info) => Ok(info),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(Info::default()),
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => Ok(Info::default()),
+        Err(e) => Err(e),
+    }
+}
+
+fn read_u32_be<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+fn read_u32_le<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_vec<R: Read>(r: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    r.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(buf)
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Parses a Vorbis comment block (shared by FLAC, Ogg Vorbis and Opus).
+fn parse_vorbis_comment(data: &[u8], tags: &mut Tags) -> io::Result<()> {
+    let mut r = data;
+    let vendor_len = read_u32_le(&mut r)? as usize;
+    r = r
+        .get(vendor_len..)
+        .ok_or_else(|| invalid("vorbis vendor"))?;
+    let count = read_u32_le(&mut r)?;
+    for _ in 0..count {
+        let len = read_u32_le(&mut r)? as usize;
+        let comment = r.get(..len).ok_or_else(|| invalid("vorbis comment"))?;
+        r = &r[len..];
+        if let Some((key, value)) = String::from_utf8_lossy(comment).split_once('=') {
+            tags.set(key, value);
+        }
+    }
+    Ok(())
+}
+
+fn read_flac<R: Read + Seek>(r: &mut R) -> io::Result<Info> {
+    let mut info = Info::default();
+    r.seek(SeekFrom::Start(4))?;
+
+    loop {
+        let header = read_u32_be(r)?;
+        let last = header & 0x8000_0000 != 0;
+        let kind = (header >> 24) & 0x7f;
+        let len = (header & 0x00ff_ffff) as usize;
+
+        match kind {
+            // STREAMINFO
+            0 => {
+                let block = read_vec(r, len)?;
+                if block.len() >= 18 {
+                    let rate = (u32::from(block[10]) << 12)
+                        | (u32::from(block[11]) << 4)
+                        | (u32::from(block[12]) >> 4);
+                    let samples = (u64::from(block[13] & 0x0f) << 32)
+                        | u64::from(u32::from_be_bytes([
+                            block[14], block[15], block[16], block[17],
+                        ]));
+                    if rate > 0 && samples > 0 {
+                        info.duration = Some(samples as f64 / f64::from(rate));
+                    }
+                }
+            }
+            // VORBIS_COMMENT
+            4 => parse_vorbis_comment(&read_vec(r, len)?, &mut info.tags)?,
+            // Pictures and padding can be large, don't read them.
+            _ => {
+                r.seek(SeekFrom::Current(len as i64))?;
+            }
+        }
+
+        if last {
+            return Ok(info);
+        }
+    }
+}
+
+/// Reads Ogg pages until `count` complete packets have been assembled.
+fn read_ogg_packets<R: Read>(r: &mut R, count: usize) -> io::Result<Vec<Vec<u8>>> {
+    let mut packets = Vec::new();
+    let mut current = Vec::new();
+
+    while packets.len() < count {
+        let mut header = [0u8; 27];
+        r.read_exact(&mut header)?;
+        if &header[..4] != b"OggS" {
+            return Err(invalid("ogg page"));
+        }
+        let segments = read_vec(r, header[26] as usize)?;
+        for &len in &segments {
+            current.extend(read_vec(r, len as usize)?);
+            // A lacing value below 255 terminates the packet.
+            if len < 255 {
+                packets.push(std::mem::take(&mut current));
+                if packets.len() == count {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(packets)
+}
+
+/// Granule position of the last Ogg page, i.e. the total number of samples.
+fn last_ogg_granule<R: Read + Seek>(r: &mut R, size: u64) -> io::Result<Option<u64>> {
+    let tail = size.min(64 * 1024);
+    r.seek(SeekFrom::Start(size - tail))?;
+    let buf = read_vec(r, tail as usize)?;
+
+    let Some(pos) = buf.windows(4).rposition(|w| w == b"OggS") else {
+        return Ok(None);
+    };
+    Ok(buf
+        .get(pos + 6..pos + 14)
+        .map(|g| u64::from_le_bytes(g.try_into().unwrap())))
+}
+
+fn read_ogg<R: Read + Seek>(r: &mut R, size: u64) -> io::Result<Info> {
+    let mut info = Info::default();
+    let packets = read_ogg_packets(r, 2)?;
+    let (head, comment) = (&packets[0], &packets[1]);
+
+    // (sample rate of the granule clock, samples to skip at the start)
+    let (rate, pre_skip) = if head.starts_with(b"\x01vorbis") && head.len() >= 16 {
+        if let Some(data) = comment.strip_prefix(b"\x03vorbis") {
+            parse_vorbis_comment(data, &mut info.tags)?;
+        }
+        (u32::from_le_bytes(head[12..16].try_into().unwrap()), 0)
+    } else if head.starts_with(b"OpusHead") && head.len() >= 12 {
+        if let Some(data) = comment.strip_prefix(b"OpusTags") {
+            parse_vorbis_comment(data, &mut info.tags)?;
+        }
+        (48_000, u16::from_le_bytes([head[10], head[11]]) as u64)
+    } else {
+        return Ok(info);
+    };
+
+    if rate > 0
+        && let Some(granule) = last_ogg_granule(r, size)?
+        && granule != u64::MAX
+    {
+        info.duration = Some(granule.saturating_sub(pre_skip) as f64 / f64::from(rate));
+    }
+    Ok(info)
+}
+
+fn read_wav<R: Read + Seek>(r: &mut R) -> io::Result<Info> {
+    let mut info = Info::default();
+    let mut header = [0u8; 12];
+    r.read_exact(&mut header)?;
+    if &header[8..12] != b"WAVE" {
+        return Ok(info);
+    }
+
+    let mut byte_rate = 0;
+    loop {
+        let mut id = [0u8; 4];
+        if r.read_exact(&mut id).is_err() {
+            return Ok(info);
+        }
+        let len = read_u32_le(r)? as u64;
+        // Chunks are padded to an even size.
+        let padded = len + (len & 1);
+
+        match &id {
+            b"fmt " => {
+                let chunk = read_vec(r, padded as usize)?;
+                if chunk.len() >= 12 {
+                    byte_rate = u32::from_le_bytes(chunk[8..12].try_into().unwrap());
+                }
+            }
+            b"data" => {
+                if byte_rate > 0 {
+                    info.duration = Some(len as f64 / f64::from(byte_rate));
+                }
+                r.seek(SeekFrom::Current(padded as i64))?;
+            }
+            b"LIST" => {
+                let chunk = read_vec(r, padded as usize)?;
+                if chunk.starts_with(b"INFO") {
+                    parse_riff_info(&chunk[4..], &mut info.tags);
+                }
+            }
+            _ => {
+                r.seek(SeekFrom::Current(padded as i64))?;
+            }
+        }
+    }
+}
+
+fn parse_riff_info(mut data: &[u8], tags: &mut Tags) {
+    while data.len() >= 8 {
+        let id = &data[..4];
+        let len = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+        let Some(value) = data.get(8..8 + len) else {
+            return;
+        };
+        let key = match id {
+            b"INAM" => "TITLE",
+            b"IART" => "ARTIST",
+            b"IPRD" => "ALBUM",
+            b"ICRD" => "DATE",
+            b"IGNR" => "GENRE",
+            b"ITRK" | b"IPRT" => "TRACKNUMBER",
+            _ => "",
+        };
+        tags.set(key, &String::from_utf8_lossy(value));
+        data = data.get(8 + len + (len & 1)..).unwrap_or_default();
+    }
+}
+
+fn syncsafe(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .fold(0, |acc, b| (acc << 7) | (*b as usize & 0x7f))
+}
+
+/// Reverses ID3 unsynchronisation (`FF 00` -> `FF`).
+fn unsynchronise(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut prev = 0;
+    for &b in data {
+        if !(prev == 0xff && b == 0) {
+            out.push(b);
+        }
+        prev = b;
+    }
+    out
+}
+
+fn decode_id3_text(data: &[u8]) -> String {
+    let Some((&encoding, text)) = data.split_first() else {
+        return String::new();
+    };
+    let text = match encoding {
+        0 => text.iter().map(|&b| b as char).collect(),
+        1 | 2 => {
+            let mut big_endian = encoding == 2;
+            let mut text = text;
+            if text.starts_with(&[0xff, 0xfe]) {
+                text = &text[2..];
+            } else if text.starts_with(&[0xfe, 0xff]) {
+                big_endian = true;
+                text = &text[2..];
+            }
+            let units: Vec<u16> = text
+                .chunks_exact(2)
+                .map(|c| {
+                    if big_endian {
+                        u16::from_be_bytes([c[0], c[1]])
+                    } else {
+                        u16::from_le_bytes([c[0], c[1]])
+                    }
+                })
+                .take_while(|&u| u != 0)
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => String::from_utf8_lossy(text).into_owned(),
+    };
+    // ID3v2.4 separates multiple values with NUL; keep the first one.
+    text.split('\0').next().unwrap_or_default().to_string()
+}
+
+/// Handles one ID3v2 frame, `data` being the frame body.
+fn apply_id3_frame(id: &str, data: &[u8], tags: &mut Tags, length_ms: &mut Option<f64>) {
+    let key = match id {
+        "TIT2" | "TT2" => "TITLE",
+        "TPE1" | "TP1" => "ARTIST",
+        "TALB" | "TAL" => "ALBUM",
+        "TRCK" | "TRK" => "TRACKNUMBER",
+        "TDRC" | "TYER" | "TYE" | "TDOR" | "TORY" => "YEAR",
+        "TCON" | "TCO" => "GENRE",
+        "TLEN" | "TLE" => {
+            *length_ms = decode_id3_text(data).trim().parse().ok();
+            return;
+        }
+        "POPM" | "POP" => {
+            apply_popm(data, tags);
+            return;
+        }
+        _ => return,
+    };
+    tags.set(key, &decode_id3_text(data));
+}
+
+/// Popularimeter: email, NUL, rating (1-255, 0 = unrated), optional counter.
+fn apply_popm(data: &[u8], tags: &mut Tags) {
+    let Some(email_end) = data.iter().position(|&b| b == 0) else {
+        return;
+    };
+    let Some((&rating, counter)) = data[email_end + 1..].split_first() else {
+        return;
+    };
+    if rating > 0 {
+        set_once(&mut tags.rating, rating as f64 / 255.0);
+    }
+    // Usually 4 bytes, but the counter may grow as needed.
+    if !counter.is_empty() && counter.len() <= 8 {
+        let count = counter.iter().fold(0u64, |n, &b| n << 8 | b as u64);
+        set_once(&mut tags.play_count, count.min(u32::MAX as u64) as u32);
+    }
+}
+
+/// Reads an ID3v2 tag at the start of the file. Returns the offset of the
+/// audio data and the `TLEN` length, if any.
+fn read_id3v2<R: Read + Seek>(r: &mut R, tags: &mut Tags) -> io::Result<(u64, Option<f64>)> {
+    let mut header = [0u8; 10];
+    r.read_exact(&mut header)?;
+    if &header[..3] != b"ID3" {
+        return Ok((0, None));
+    }
+    let version = header[3];
+    let flags = header[5];
+    let tag_size = syncsafe(&header[6..10]);
+    let audio_start = 10 + tag_size as u64 + if flags & 0x10 != 0 { 10 } else { 0 };
+
+    let mut body = read_vec(r, tag_size)?;
+    if flags & 0x80 != 0 && version < 4 {
+        body = unsynchronise(&body);
+    }
+    let mut pos = 0;
+    if flags & 0x40 != 0 && body.len() >= 4 {
+        pos = match version {
+            3 => 4 + u32::from_be_bytes(body[..4].try_into().unwrap()) as usize,
+            4 => syncsafe(&body[..4]),
+            _ => 0,
+        };
+    }
+
+    let (id_len, header_len) = if version == 2 { (3, 6) } else { (4, 10) };
+    let mut length_ms = None;
+    while pos + header_len <= body.len() {
+        let frame = &body[pos..pos + header_len];
+        if frame[0] == 0 {
+            // Padding
+            break;
+        }
+        let id = String::from_utf8_lossy(&frame[..id_len]).into_owned();
+        let size = match version {
+            2 => (frame[3] as usize) << 16 | (frame[4] as usize) << 8 | frame[5] as usize,
+            3 => u32::from_be_bytes(frame[4..8].try_into().unwrap()) as usize,
+            _ => syncsafe(&frame[4..8]),
+        };
+        let start = pos + header_len;
+        let Some(data) = body.get(start..start + size) else {
+            break;
+        };
+
+        if let Some(data) = frame_body(version, frame.get(9).copied().unwrap_or(0), data) {
+            apply_id3_frame(&id, &data, tags, &mut length_ms);
+        }
+        pos = start + size;
+    }
+
+    Ok((audio_start, length_ms.map(|ms| ms / 1000.0)))
+}
+
+/// The frame data without the bytes its format flags put in front of it.
+/// `None` for compressed or encrypted frames, which are skipped.
+fn frame_body(version: u8, format_flags: u8, data: &[u8]) -> Option<Cow<'_, [u8]>> {
+    let skip = match version {
+        4 if format_flags & 0x0c != 0 => return None,
+        // Grouping identity, then the data length indicator.
+        4 => {
+            (if format_flags & 0x40 != 0 { 1 } else { 0 })
+                + if format_flags & 0x01 != 0 { 4 } else { 0 }
+        }
+        3 if format_flags & 0xc0 != 0 => return None,
+        // Grouping identity.
+        3 if format_flags & 0x20 != 0 => 1,
+        _ => 0,
+    };
+    let data = data.get(skip..)?;
+    Some(if version == 4 && format_flags & 0x02 != 0 {
+        Cow::Owned(unsynchronise(data))
+    } else {
+        Cow::Borrowed(data)
+    })
+}
+
+fn read_id3v1<R: Read + Seek>(r: &mut R, size: u64, tags: &mut Tags) -> io::Result<()> {
+    if size < 128 {
+        return Ok(());
+    }
+    r.seek(SeekFrom::Start(size - 128))?;
+    let tag = read_vec(r, 128)?;
+    if &tag[..3] != b"TAG" {
+        return Ok(());
+    }
+
+    let text = |bytes: &[u8]| -> String { bytes.iter().map(|&b| b as char).collect() };
+    tags.set("TITLE", &text(&tag[3..33]));
+    tags.set("ARTIST", &text(&tag[33..63]));
+    tags.set("ALBUM", &text(&tag[63..93]));
+    tags.set("YEAR", &text(&tag[93..97]));
+    // ID3v1.1 stores the track number in the last comment byte.
+    if tag[125] == 0 && tag[126] != 0 {
+        tags.set("TRACKNUMBER", &tag[126].to_string());
+    }
+    if let Some(genre) = GENRES.get(tag[127] as usize) {
+        tags.set("GENRE", genre);
+    }
+    Ok(())
+}
+
+/// Duration of an MPEG audio stream from its Xing/Info/VBRI header, or
+/// estimated from the first frame's bitrate for CBR files.
+fn mpeg_duration<R: Read + Seek>(
+    r: &mut R,
+    audio_start: u64,
+    size: u64,
+) -> io::Result<Option<f64>> {
+    r.seek(SeekFrom::Start(audio_start))?;
+    let buf = read_vec(r, (size - audio_start).min(64 * 1024) as usize)?;
+
+    let Some(pos) = buf
+        .windows(2)
+        .position(|w| w[0] == 0xff && w[1] & 0xe0 == 0xe0 && w[1] & 0x06 != 0)
+    else {
+        return Ok(None);
+    };
+    let Some(header) = buf.get(pos..pos + 4) else {
+        return Ok(None);
+    };
+
+    // 3 = MPEG1, 2 = MPEG2, 0 = MPEG2.5
+    let version = (header[1] >> 3) & 0x03;
+    let layer = (header[1] >> 1) & 0x03;
+    let bitrate_idx = (header[2] >> 4) as usize;
+    let rate_idx = ((header[2] >> 2) & 0x03) as usize;
+    let mono = header[3] >> 6 == 3;
+    if version == 1 || rate_idx == 3 || bitrate_idx == 0 || bitrate_idx == 15 {
+        return Ok(None);
+    }
+
+    let sample_rate = [44100, 48000, 32000][rate_idx]
+        / match version {
+            3 => 1,
+            2 => 2,
+            _ => 4,
+        };
+    let samples_per_frame = match (version, layer) {
+        (_, 3) => 384,
+        (3, _) | (_, 2) => 1152,
+        _ => 576,
+    };
+    let bitrate_kbps = match (version, layer) {
+        (3, 3) => [
+            0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448,
+        ],
+        (3, 2) => [
+            0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384,
+        ],
+        (3, _) => [
+            0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+        ],
+        (_, 3) => [
+            0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256,
+        ],
+        _ => [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+    }[bitrate_idx];
+
+    let side_info = match (version == 3, mono) {
+        (true, false) => 32,
+        (true, true) | (false, false) => 17,
+        (false, true) => 9,
+    };
+    let u32_at = |offset: usize| {
+        buf.get(offset..offset + 4)
+            .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+    };
+
+    let xing = pos + 4 + side_info;
+    let vbri = pos + 36;
+    let frame_count = match (buf.get(xing..xing + 8), buf.get(vbri..vbri + 4)) {
+        // The lowest flag bit says whether the frame count is present.
+        (Some(x), _) if (&x[..4] == b"Xing" || &x[..4] == b"Info") && x[7] & 0x01 != 0 => {
+            u32_at(xing + 8)
+        }
+        (_, Some(b"VBRI")) => u32_at(vbri + 14),
+        _ => None,
+    };
+
+    Ok(match frame_count {
+        Some(frames) if frames > 0 => {
+            Some(f64::from(frames) * f64::from(samples_per_frame) / f64::from(sample_rate))
+        }
+        _ => {
+            let audio_len = size - audio_start - pos as u64;
+            Some(audio_len as f64 * 8.0 / (f64::from(bitrate_kbps) * 1000.0))
+        }
+    })
+}
+
+fn read_mpeg<R: Read + Seek>(r: &mut R, size: u64) -> io::Result<Info> {
+    let mut info = Info::default();
+    let (audio_start, length) = read_id3v2(r, &mut info.tags)?;
+    read_id3v1(r, size, &mut info.tags)?;
+
+    info.duration = match length {
+        Some(length) if length > 0.0 => Some(length),
+        _ if audio_start < size => mpeg_duration(r, audio_start, size)?,
+        _ => None,
+    };
+    Ok(info)
+}
+
+/// Resolves ID3 numeric genres such as `(17)` or `17` to their names.
+fn genre_name(value: &str) -> String {
+    let id = value
+        .strip_prefix('(')
+        .and_then(|v| v.split_once(')'))
+        .map_or(value, |(id, _)| id);
+    id.parse::<usize>()
+        .ok()
+        .and_then(|id| GENRES.get(id))
+        .map_or_else(|| value.to_string(), |g| g.to_string())
+}
+
+const GENRES: [&str; 80] = [
+    "Blues",
+    "Classic Rock",
+    "Country",
+    "Dance",
+    "Disco",
+    "Funk",
+    "Grunge",
+    "Hip-Hop",
+    "Jazz",
+    "Metal",
+    "New Age",
+    "Oldies",
+    "Other",
+    "Pop",
+    "R&B",
+    "Rap",
+    "Reggae",
+    "Rock",
+    "Techno",
+    "Industrial",
+    "Alternative",
+    "Ska",
+    "Death Metal",
+    "Pranks",
+    "Soundtrack",
+    "Euro-Techno",
+    "Ambient",
+    "Trip-Hop",
+    "Vocal",
+    "Jazz+Funk",
+    "Fusion",
+    "Trance",
+    "Classical",
+    "Instrumental",
+    "Acid",
+    "House",
+    "Game",
+    "Sound Clip",
+    "Gospel",
+    "Noise",
+    "AlternRock",
+    "Bass",
+    "Soul",
+    "Punk",
+    "Space",
+    "Meditative",
+    "Instrumental Pop",
+    "Instrumental Rock",
+    "Ethnic",
+    "Gothic",
+    "Darkwave",
+    "Techno-Industrial",
+    "Electronic",
+    "Pop-Folk",
+    "Eurodance",
+    "Dream",
+    "Southern Rock",
+    "Comedy",
+    "Cult",
+    "Gangsta",
+    "Top 40",
+    "Christian Rap",
+    "Pop/Funk",
+    "Jungle",
+    "Native American",
+    "Cabaret",
+    "New Wave",
+    "Psychadelic",
+    "Rave",
+    "Showtunes",
+    "Trailer",
+    "Lo-Fi",
+    "Tribal",
+    "Acid Punk",
+    "Acid Jazz",
+    "Polka",
+    "Retro",
+    "Musical",
+    "Rock & Roll",
+    "Hard Rock",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn syncsafe_bytes(n: usize) -> [u8; 4] {
+        [
+            (n >> 21 & 0x7f) as u8,
+            (n >> 14 & 0x7f) as u8,
+            (n >> 7 & 0x7f) as u8,
+            (n & 0x7f) as u8,
+        ]
+    }
+
+    fn frame(version: u8, id: &str, flags: u8, body: &[u8]) -> Vec<u8> {
+        let size = match version {
+            3 => (body.len() as u32).to_be_bytes(),
+            _ => syncsafe_bytes(body.len()),
+        };
+        [id.as_bytes(), &size, &[0, flags], body].concat()
+    }
+
+    /// A tag of `frames` followed by some padding and a byte of "audio".
+    fn tag(version: u8, frames: &[Vec<u8>]) -> Vec<u8> {
+        let body = [frames.concat(), vec![0; 16]].concat();
+        let header = [
+            b"ID3".as_slice(),
+            &[version, 0, 0],
+            &syncsafe_bytes(body.len()),
+        ]
+        .concat();
+        [header, body, vec![0xff]].concat()
+    }
+
+    fn read(tag: Vec<u8>) -> (Tags, u64, Option<f64>) {
+        let mut tags = Tags::default();
+        let (audio_start, length) = read_id3v2(&mut Cursor::new(tag), &mut tags).unwrap();
+        (tags, audio_start, length)
+    }
+
+    #[test]
+    fn id3v23_frames() {
+        let tag = tag(
+            3,
+            &[
+                frame(3, "TIT2", 0, b"\x00Blue Train"),
+                // Grouping identity byte in front of the text.
+                frame(3, "TPE1", 0x20, b"\x07\x03John Coltrane"),
+                // Encrypted, can't be read.
+                frame(3, "TALB", 0x40, b"\x01\x00Secret"),
+                frame(3, "TRCK", 0, b"\x001/5"),
+                frame(3, "TLEN", 0, b"\x00643000"),
+                frame(3, "TCON", 0, b"\x01\xff\xfeJ\x00a\x00z\x00z\x00\x00\x00"),
+            ],
+        );
+        let len = tag.len() as u64;
+        let (tags, audio_start, length) = read(tag);
+        assert_eq!(tags.title.as_deref(), Some("Blue Train"));
+        assert_eq!(tags.artist.as_deref(), Some("John Coltrane"));
+        assert_eq!(tags.album, None);
+        assert_eq!(tags.track, Some(1));
+        assert_eq!(tags.genre.as_deref(), Some("Jazz"));
+        assert_eq!(length, Some(643.0));
+        assert_eq!(audio_start, len - 1);
+    }
+
+    #[test]
+    fn id3v24_format_flags() {
+        // Unsynchronised, with a data length indicator: `FF 00` reads as `FF`.
+        let title = b"\x00A\xff\x00B";
+        let title = [syncsafe_bytes(4).as_slice(), title].concat();
+        let tag = tag(
+            4,
+            &[
+                frame(4, "TIT2", 0x03, &title),
+                // Grouping identity and data length indicator.
+                frame(
+                    4,
+                    "TPE1",
+                    0x41,
+                    &[&[0x07][..], &syncsafe_bytes(13), b"\x03Miles Davis"].concat(),
+                ),
+                // Compressed, can't be read.
+                frame(4, "TALB", 0x09, b"\x00\x00\x00\x0fxxxx"),
+                frame(4, "TDRC", 0, b"\x031959-08-17\x00"),
+                frame(4, "POPM", 0, b"me@example.com\x00\xff\x00\x00\x00\x07"),
+            ],
+        );
+        let (tags, _, _) = read(tag);
+        assert_eq!(tags.title.as_deref(), Some("A\u{ff}B"));
+        assert_eq!(tags.artist.as_deref(), Some("Miles Davis"));
+        assert_eq!(tags.album, None);
+        assert_eq!(tags.year, Some(1959));
+        assert_eq!(tags.rating, Some(1.0));
+        assert_eq!(tags.play_count, Some(7));
+    }
+
+    #[test]
+    fn no_id3v2_tag() {
+        let (tags, audio_start, _) = read(b"fLaC\0\0\0\0\0\0".to_vec());
+        assert_eq!(tags, Tags::default());
+        assert_eq!(audio_start, 0);
+    }
+}
@@ -1,10 +1,34 @@
 use std::{
     path::PathBuf,
-    process::{Command, Stdio},
+    process::{Child, Command, Output, Stdio},
 };
 
 use std::io::{self, Write};
 
+use crate::error::Error;
+use crate::library::Track;
+
+/// Starts fzf with piped stdin and stdout.
+fn spawn(cmd: &mut Command) -> io::Result<Child> {
+    cmd.stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => Error::FzfMissing.into(),
+            _ => e,
+        })
+}
+
+/// Waits for fzf. Esc, Ctrl-C and an empty match (130 and 1) cancel.
+fn finish(child: Child) -> io::Result<Output> {
+    let output = child.wait_with_output()?;
+    match output.status.code() {
+        Some(0) => Ok(output),
+        Some(1 | 130) => Err(Error::Cancelled.into()),
+        _ => Err(io::Error::other(format!("fzf failed: {}", output.status))),
+    }
+}
+
 pub fn run_fzf(files: &[PathBuf], multi: bool) -> io::Result<Vec<PathBuf>> {
     let mut cmd = Command::new("fzf");
 
@@ -12,7 +36,7 @@ pub fn run_fzf(files: &[PathBuf], multi: bool) -> io::Result<Vec<PathBuf>> {
         cmd.arg("-m");
     }
 
-    let mut child = cmd.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+    let mut child = spawn(&mut cmd)?;
 
     {
         let mut stdin = child.stdin.take().expect("Failed to open fzf stdin");
@@ -22,16 +46,52 @@ pub fn run_fzf(files: &[PathBuf], multi: bool) -> io::Result<Vec<PathBuf>> {
         drop(stdin);
     }
 
-    let output = child.wait_with_output()?;
+    let output = finish(child)?;
+
+    let selected = String::from_utf8_lossy(&output.stdout);
+
+    Ok(selected
+        .lines()
+        .map(|line| PathBuf::from(line.trim()))
+        .collect())
+}
 
-    if !output.status.success() {
-        return Ok(Vec::new());
+/// Like `run_fzf`, but shows labels and returns the indices of the chosen ones.
+pub fn run_fzf_labeled(labels: &[String], multi: bool) -> io::Result<Vec<usize>> {
+    let mut cmd = Command::new("fzf");
+    cmd.args(["--delimiter", "\t", "--with-nth", "2.."]);
+
+    if multi {
+        cmd.arg("-m");
+    }
+
+    let mut child = spawn(&mut cmd)?;
+
+    {
+        let mut stdin = child.stdin.take().expect("Failed to open fzf stdin");
+        for (i, label) in labels.iter().enumerate() {
+            writeln!(stdin, "{}\t{}", i, label)?;
+        }
+        drop(stdin);
     }
 
+    let output = finish(child)?;
+
     let selected = String::from_utf8_lossy(&output.stdout);
 
     Ok(selected
         .lines()
-        .map(|line| PathBuf::from(line.trim()))
+        .filter_map(|line| line.split_once('\t'))
+        .filter_map(|(i, _)| i.parse().ok())
+        .collect())
+}
+
+/// Lets the user pick tracks by their tags. Tracks of a CUE image share a
+/// path, so the selection is returned as tracks rather than paths.
+pub fn select_tracks(tracks: &[Track], multi: bool) -> io::Result<Vec<Track>> {
+    let labels: Vec<_> = tracks.iter().map(Track::label).collect();
+    Ok(run_fzf_labeled(&labels, multi)?
+        .into_iter()
+        .filter_map(|i| tracks.get(i).cloned())
         .collect())
 }
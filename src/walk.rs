@@ -0,0 +1,197 @@
+use std::collections::HashSet;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+use crate::glob;
+
+/// A file found under one of the music roots.
+pub struct MusicFile {
+    pub root: PathBuf,
+    pub path: PathBuf,
+}
+
+pub struct WalkOptions<'a> {
+    pub exclude: &'a [String],
+    /// Descend into symlinked directories. Loops are detected either way.
+    pub follow_symlinks: bool,
+    pub threads: usize,
+}
+
+/// Result of a walk. Unreadable directories don't abort the walk, they are
+/// reported in `warnings` instead.
+#[derive(Default)]
+pub struct Walk {
+    pub files: Vec<MusicFile>,
+    pub warnings: Vec<String>,
+    /// Roots that could not be read at all, e.g. an unmounted NAS.
+    pub unreachable_roots: Vec<PathBuf>,
+}
+
+struct Dir {
+    root: PathBuf,
+    path: PathBuf,
+}
+
+#[derive(Default)]
+struct State {
+    queue: Vec<Dir>,
+    /// Directories currently being read by a worker.
+    active: usize,
+    /// (device, inode) of every directory queued so far.
+    visited: HashSet<(u64, u64)>,
+    walk: Walk,
+}
+
+/// Contents of a single directory, gathered without holding the lock.
+#[derive(Default)]
+struct Listing {
+    dirs: Vec<((u64, u64), PathBuf)>,
+    files: Vec<PathBuf>,
+    warnings: Vec<String>,
+}
+
+/// Recursively lists all files under `roots` using a pool of worker threads.
+/// The returned files are sorted by path.
+pub fn walk(roots: &[PathBuf], options: &WalkOptions) -> Walk {
+    let mut state = State::default();
+    for root in roots {
+        match fs::metadata(root) {
+            Ok(meta) => {
+                if state.visited.insert((meta.dev(), meta.ino())) {
+                    state.queue.push(Dir {
+                        root: root.clone(),
+                        path: root.clone(),
+                    });
+                }
+            }
+            Err(e) => {
+                state
+                    .walk
+                    .warnings
+                    .push(format!("{}: {}", root.display(), e));
+                state.walk.unreachable_roots.push(root.clone());
+            }
+        }
+    }
+
+    let state = Mutex::new(state);
+    let wakeup = Condvar::new();
+
+    thread::scope(|scope| {
+        for _ in 0..options.threads.max(1) {
+            scope.spawn(|| worker(&state, &wakeup, options));
+        }
+    });
+
+    let mut walk = state.into_inner().unwrap().walk;
+    walk.files.sort_by(|a, b| a.path.cmp(&b.path));
+    walk
+}
+
+fn worker(state: &Mutex<State>, wakeup: &Condvar, options: &WalkOptions) {
+    loop {
+        let dir = {
+            let mut guard = state.lock().unwrap();
+            loop {
+                if let Some(dir) = guard.queue.pop() {
+                    guard.active += 1;
+                    break dir;
+                }
+                if guard.active == 0 {
+                    // Nothing queued and nobody left to queue more.
+                    wakeup.notify_all();
+                    return;
+                }
+                guard = wakeup.wait(guard).unwrap();
+            }
+        };
+
+        let listing = read_dir(&dir, options);
+
+        let mut guard = state.lock().unwrap();
+        for (id, path) in listing.dirs {
+            if guard.visited.insert(id) {
+                guard.queue.push(Dir {
+                    root: dir.root.clone(),
+                    path,
+                });
+            }
+        }
+        guard
+            .walk
+            .files
+            .extend(listing.files.into_iter().map(|path| MusicFile {
+                root: dir.root.clone(),
+                path,
+            }));
+        guard.walk.warnings.extend(listing.warnings);
+        guard.active -= 1;
+        wakeup.notify_all();
+    }
+}
+
+fn is_excluded(root: &Path, path: &Path, exclude: &[String], is_dir: bool) -> bool {
+    let relative = path.strip_prefix(root).unwrap_or(path).to_string_lossy();
+    exclude
+        .iter()
+        .any(|pattern| glob::matches(pattern, &relative, is_dir))
+}
+
+fn read_dir(dir: &Dir, options: &WalkOptions) -> Listing {
+    let mut listing = Listing::default();
+    let entries = match fs::read_dir(&dir.path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            listing
+                .warnings
+                .push(format!("{}: {}", dir.path.display(), e));
+            return listing;
+        }
+    };
+
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                listing
+                    .warnings
+                    .push(format!("{}: {}", dir.path.display(), e));
+                continue;
+            }
+        };
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+
+        // Resolve symlinks; dangling ones are silently skipped.
+        let meta = if file_type.is_symlink() {
+            match fs::metadata(&path) {
+                Ok(meta) => meta,
+                Err(_) => continue,
+            }
+        } else {
+            match entry.metadata() {
+                Ok(meta) => meta,
+                Err(e) => {
+                    listing.warnings.push(format!("{}: {}", path.display(), e));
+                    continue;
+                }
+            }
+        };
+
+        if meta.is_dir() {
+            if (options.follow_symlinks || !file_type.is_symlink())
+                && !is_excluded(&dir.root, &path, options.exclude, true)
+            {
+                listing.dirs.push(((meta.dev(), meta.ino()), path));
+            }
+        } else if meta.is_file() && !is_excluded(&dir.root, &path, options.exclude, false) {
+            listing.files.push(path);
+        }
+    }
+    listing
+}